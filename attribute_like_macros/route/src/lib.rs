@@ -0,0 +1,296 @@
+// #[route(METHOD, "/path")]: the attribute described in attribute_like_macros.
+//
+//   #[route(GET, "/posts/{id}")]
+//   fn show_post(id: u64) -> String {
+//     format!("post {}", id)
+//   }
+//
+// leaves show_post as it is and adds a function registering it with
+// the router of multithreaded_web_server:
+//
+//   fn register_show_post(router: &mut Router) {
+//     router.add(Method::Get, "/posts/{id}", |request| {
+//       // {id} parsed into a u64, 400 Bad Request if it isn't one.
+//       ...
+//       Response::from(show_post(id))
+//     });
+//   }
+//
+// Every {param} of the path has to be an argument of the function
+// and the other way around, so a typo in either is a compile error
+// instead of a route that never matches. An argument of type
+// &Request gets the whole request instead.
+extern crate proc_macro;
+
+use std::collections::HashSet;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+  parse::{Parse, ParseStream},
+  spanned::Spanned,
+  FnArg, Ident, ItemFn, LitStr, Pat, Token, Type,
+};
+
+struct RouteArgs {
+  method: Ident,
+  path: LitStr,
+}
+
+impl Parse for RouteArgs {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let method = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let path = input.parse()?;
+    // A trailing comma is fine.
+    if input.peek(Token![,]) {
+      input.parse::<Token![,]>()?;
+    }
+
+    Ok(RouteArgs { method, path })
+  }
+}
+
+/// The variant of multithreaded_web_server::Method for `method`.
+fn method_variant(method: &Ident) -> syn::Result<Ident> {
+  let variant = match method.to_string().as_str() {
+    "GET" => "Get",
+    "POST" => "Post",
+    "PUT" => "Put",
+    "PATCH" => "Patch",
+    "DELETE" => "Delete",
+    "HEAD" => "Head",
+    _ => {
+      return Err(syn::Error::new(
+        method.span(),
+        "expected one of GET, POST, PUT, PATCH, DELETE or HEAD",
+      ))
+    }
+  };
+
+  Ok(Ident::new(variant, method.span()))
+}
+
+/// The {params} of `path`, checking it is a path the router understands.
+fn path_params(path: &LitStr) -> syn::Result<Vec<String>> {
+  let value = path.value();
+
+  if !value.starts_with('/') {
+    return Err(syn::Error::new(path.span(), "the path must start with /"));
+  }
+
+  let mut params = Vec::new();
+
+  for segment in value.split('/').filter(|segment| !segment.is_empty()) {
+    match segment.strip_prefix('{') {
+      Some(rest) => {
+        let name = rest.strip_suffix('}').ok_or_else(|| {
+          syn::Error::new(
+            path.span(),
+            format!("`{}` is missing its closing }}", segment),
+          )
+        })?;
+
+        if syn::parse_str::<Ident>(name).is_err() {
+          return Err(syn::Error::new(
+            path.span(),
+            format!("`{}` isn't a valid parameter name", name),
+          ));
+        }
+
+        if params.iter().any(|param| param == name) {
+          return Err(syn::Error::new(
+            path.span(),
+            format!("`{}` appears more than once in the path", name),
+          ));
+        }
+
+        params.push(name.to_string());
+      }
+      None if segment.contains('{') || segment.contains('}') => {
+        return Err(syn::Error::new(
+          path.span(),
+          format!(
+            "`{}`: a parameter has to be a whole segment, as in /{{id}}",
+            segment
+          ),
+        ))
+      }
+      None => {}
+    }
+  }
+
+  Ok(params)
+}
+
+/// Whether `ty` is &Request. Other references are errors,
+/// path parameters are parsed into owned values.
+fn is_request(ty: &Type) -> syn::Result<bool> {
+  let reference = match ty {
+    Type::Reference(reference) => reference,
+    _ => return Ok(false),
+  };
+
+  let request = match &*reference.elem {
+    Type::Path(path) => path
+      .path
+      .segments
+      .last()
+      .is_some_and(|segment| segment.ident == "Request"),
+    _ => false,
+  };
+
+  if !request {
+    return Err(syn::Error::new(
+      ty.span(),
+      "path parameters are parsed with FromStr into owned values, \
+       use an owned type like String, or &Request for the whole request",
+    ));
+  }
+
+  if let Some(mutability) = &reference.mutability {
+    return Err(syn::Error::new(
+      mutability.span(),
+      "handlers can't change the request, take &Request instead",
+    ));
+  }
+
+  Ok(true)
+}
+
+fn expand(args: RouteArgs, function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+  let method = method_variant(&args.method)?;
+  let params = path_params(&args.path)?;
+  let path = &args.path;
+
+  let name = &function.sig.ident;
+  let vis = &function.vis;
+
+  if let Some(asyncness) = &function.sig.asyncness {
+    return Err(syn::Error::new(
+      asyncness.span(),
+      "the router only calls plain functions, not async ones",
+    ));
+  }
+
+  if !function.sig.generics.params.is_empty() {
+    return Err(syn::Error::new(
+      function.sig.generics.span(),
+      "route handlers can't be generic",
+    ));
+  }
+
+  // How each argument of the handler is filled in from the request.
+  let mut bindings = Vec::new();
+  let mut arguments = Vec::new();
+  let mut used = HashSet::new();
+
+  for input in &function.sig.inputs {
+    let typed = match input {
+      FnArg::Typed(typed) => typed,
+      FnArg::Receiver(receiver) => {
+        return Err(syn::Error::new(
+          receiver.span(),
+          "route handlers are plain functions, not methods",
+        ))
+      }
+    };
+
+    let ident = match &*typed.pat {
+      Pat::Ident(pat) => &pat.ident,
+      pat => {
+        return Err(syn::Error::new(
+          pat.span(),
+          "route handler arguments have to be named after a path parameter",
+        ))
+      }
+    };
+
+    if is_request(&typed.ty)? {
+      arguments.push(quote! { request });
+      continue;
+    }
+
+    let param = ident.to_string();
+    let param = param.trim_start_matches("r#");
+
+    if !params.iter().any(|p| p == param) {
+      return Err(syn::Error::new(
+        ident.span(),
+        format!(
+          "`{}` isn't a parameter of {}, which has {}",
+          param,
+          path.value(),
+          if params.is_empty() {
+            "none".to_string()
+          } else {
+            params
+              .iter()
+              .map(|param| format!("{{{}}}", param))
+              .collect::<Vec<_>>()
+              .join(", ")
+          }
+        ),
+      ));
+    }
+
+    used.insert(param.to_string());
+
+    let ty = &typed.ty;
+    let value = format_ident!("{}", param, span = Span::mixed_site());
+    bindings.push(quote! {
+      let #value: #ty = match request.param(#param).unwrap().parse() {
+        Ok(value) => value,
+        Err(_) => {
+          return ::multithreaded_web_server::Response::bad_request(
+            format!("invalid {}: {}", #param, request.param(#param).unwrap()),
+          )
+        }
+      };
+    });
+    arguments.push(quote! { #value });
+  }
+
+  if let Some(param) = params.iter().find(|param| !used.contains(*param)) {
+    return Err(syn::Error::new(
+      path.span(),
+      format!("{{{}}} isn't an argument of `{}`", param, name),
+    ));
+  }
+
+  let register = format_ident!("register_{}", name);
+  let doc = format!(
+    "Registers [`{}`] as the handler of `{} {}`.",
+    name,
+    args.method,
+    path.value()
+  );
+
+  Ok(quote! {
+    #function
+
+    #[doc = #doc]
+    #vis fn #register(router: &mut ::multithreaded_web_server::Router) {
+      router.add(
+        ::multithreaded_web_server::Method::#method,
+        #path,
+        |request: &::multithreaded_web_server::Request| {
+          #(#bindings)*
+          ::multithreaded_web_server::Response::from(#name(#(#arguments),*))
+        },
+      );
+    }
+  })
+}
+
+/// Makes the function the handler of a route, see the top of this file.
+#[proc_macro_attribute]
+pub fn route(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let args = syn::parse_macro_input!(attr as RouteArgs);
+  let function = syn::parse_macro_input!(item as ItemFn);
+
+  expand(args, function)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
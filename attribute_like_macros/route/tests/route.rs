@@ -0,0 +1,63 @@
+use multithreaded_web_server::{Method, Request, Response, Router};
+use route::route;
+
+#[route(GET, "/")]
+fn index() -> &'static str {
+  "index"
+}
+
+#[route(GET, "/posts/{id}/comments/{comment}")]
+fn show_comment(comment: usize, id: u64) -> String {
+  format!("comment {} of post {}", comment, id)
+}
+
+#[route(DELETE, "/posts/{id}")]
+pub fn delete_post(request: &Request, id: u64) -> Response {
+  Response::ok(format!("{:?} {}", request.method, id))
+}
+
+fn router() -> Router {
+  let mut router = Router::new();
+  register_index(&mut router);
+  register_show_comment(&mut router);
+  register_delete_post(&mut router);
+  router
+}
+
+#[test]
+fn handlers_stay_callable() {
+  assert_eq!("index", index());
+  assert_eq!("comment 1 of post 2", show_comment(1, 2));
+}
+
+#[test]
+fn registers_the_handlers() {
+  let router = router();
+
+  assert_eq!(
+    Response::ok("index"),
+    router.handle(Request::new(Method::Get, "/"))
+  );
+  assert_eq!(
+    Response::ok("comment 7 of post 3"),
+    router.handle(Request::new(Method::Get, "/posts/3/comments/7"))
+  );
+  assert_eq!(
+    Response::ok("Delete 3"),
+    router.handle(Request::new(Method::Delete, "/posts/3"))
+  );
+}
+
+#[test]
+fn rejects_params_of_the_wrong_type() {
+  assert_eq!(
+    Response::bad_request("invalid id: three"),
+    router().handle(Request::new(Method::Get, "/posts/three/comments/7"))
+  );
+}
+
+#[test]
+fn ui() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,8 @@
+use route::route;
+
+#[route(GET, "/posts/post-{id}")]
+fn show_post(id: u64) -> String {
+  format!("post {}", id)
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use route::route;
+
+#[route(GET, "/posts/{id}/comments/{comment}")]
+fn show_comment(id: u64) -> String {
+  format!("post {}", id)
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use route::route;
+
+#[route(GET, "/posts/{id}")]
+fn show_post(id: &str) -> String {
+  id.to_string()
+}
+
+#[route(GET, "/posts")]
+fn list_posts(request: &mut multithreaded_web_server::Request) -> String {
+  request.path.clone()
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use route::route;
+
+#[route(FETCH, "/")]
+fn index() -> &'static str {
+  "index"
+}
+
+fn main() {}
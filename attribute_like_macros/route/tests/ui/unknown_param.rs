@@ -0,0 +1,8 @@
+use route::route;
+
+#[route(GET, "/posts/{id}")]
+fn show_post(post_id: u64) -> String {
+  format!("post {}", post_id)
+}
+
+fn main() {}
@@ -1,3 +1,6 @@
+use multithreaded_web_server::{Method, Request, Response, Router};
+use route::route;
+
 /// Attribute-like macros
 ///
 /// Attribute-like macros are similar to custom derive macros,
@@ -29,7 +32,28 @@
 /// Other than that, attribute-like macros wotk the same way as custom drive
 /// macros: you create a crate with the proc-macro crate type and implement
 /// a function that generates the code you want.
-
+///
+/// The route crate next to this one implements #[route] for the
+/// router of multithreaded_web_server.
 fn main() {
-  println!("Hello, world!");
+  let mut router = Router::new();
+  register_index(&mut router);
+  register_show_post(&mut router);
+
+  for path in &["/", "/posts/1", "/posts/first", "/about"] {
+    let response: Response = router.handle(Request::new(Method::Get, path));
+    println!("GET {} -> {} {}", path, response.status, response.body);
+  }
+}
+
+#[route(GET, "/")]
+fn index() -> &'static str {
+  "Hello, world!"
+}
+
+// {id} has to be an argument of the function, and is parsed
+// into its type before the function is called.
+#[route(GET, "/posts/{id}")]
+fn show_post(id: u64) -> String {
+  format!("post number {}", id)
 }
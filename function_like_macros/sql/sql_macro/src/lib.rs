@@ -0,0 +1,568 @@
+// sql!: checks a query at compile time.
+//
+// The subset of SQL understood is:
+//
+//   SELECT * | column, ... FROM table [WHERE condition]
+//     [ORDER BY column [ASC | DESC], ...] [LIMIT count]
+//   INSERT INTO table (column, ...) VALUES (value, ...)
+//   UPDATE table SET column = value, ... [WHERE condition]
+//   DELETE FROM table [WHERE condition]
+//
+// where a condition combines comparisons (= != <> < <= > >=, IS [NOT] NULL)
+// with AND, OR, NOT and parentheses, and a value is a column, a number,
+// a "string", TRUE, FALSE, NULL or a parameter written :name.
+// Keywords can be written in any case.
+//
+// The query is given as Rust tokens, so strings use double quotes
+// and come out of the macro as SQL's single-quoted strings.
+//
+// Mistakes become compile errors pointing at the token in question,
+// and the query expands to a value with a bind_name method per
+// parameter, see the sql crate.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, Literal, Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+
+const KEYWORDS: &[&str] = &[
+  "SELECT", "FROM", "WHERE", "ORDER", "BY", "ASC", "DESC", "LIMIT", "INSERT", "INTO", "VALUES",
+  "UPDATE", "SET", "DELETE", "AND", "OR", "NOT", "IS", "NULL", "TRUE", "FALSE",
+];
+
+#[derive(Debug, Clone)]
+enum Tok {
+  Word(String, Span),
+  /// Punctuation, with <= >= != and <> already joined.
+  Sym(String, Span),
+  Lit(Literal),
+  Open(Span),
+  Close(Span),
+}
+
+impl Tok {
+  fn span(&self) -> Span {
+    match self {
+      Tok::Word(_, span) | Tok::Sym(_, span) | Tok::Open(span) | Tok::Close(span) => *span,
+      Tok::Lit(literal) => literal.span(),
+    }
+  }
+
+  fn describe(&self) -> String {
+    match self {
+      Tok::Word(word, _) => format!("`{}`", word),
+      Tok::Sym(sym, _) => format!("`{}`", sym),
+      Tok::Lit(literal) => format!("`{}`", literal),
+      Tok::Open(_) => "`(`".to_string(),
+      Tok::Close(_) => "`)`".to_string(),
+    }
+  }
+}
+
+fn flatten(input: TokenStream2, toks: &mut Vec<Tok>) -> syn::Result<()> {
+  let mut trees = input.into_iter().peekable();
+
+  while let Some(tree) = trees.next() {
+    match tree {
+      TokenTree::Ident(ident) => toks.push(Tok::Word(ident.to_string(), ident.span())),
+      TokenTree::Literal(literal) => toks.push(Tok::Lit(literal)),
+      TokenTree::Punct(punct) => {
+        let mut sym = punct.as_char().to_string();
+
+        if let Some(TokenTree::Punct(next)) = trees.peek() {
+          let joined = format!("{}{}", sym, next.as_char());
+          if ["<=", ">=", "!=", "<>"].contains(&joined.as_str()) {
+            sym = joined;
+            trees.next();
+          }
+        }
+
+        toks.push(Tok::Sym(sym, punct.span()));
+      }
+      TokenTree::Group(group) => match group.delimiter() {
+        Delimiter::Parenthesis => {
+          toks.push(Tok::Open(group.span_open()));
+          flatten(group.stream(), toks)?;
+          toks.push(Tok::Close(group.span_close()));
+        }
+        // Tokens passed through a macro_rules! macro.
+        Delimiter::None => flatten(group.stream(), toks)?,
+        _ => {
+          return Err(syn::Error::new(
+            group.span(),
+            "only parentheses can group things in SQL",
+          ))
+        }
+      },
+    }
+  }
+
+  Ok(())
+}
+
+struct Parser {
+  toks: Vec<Tok>,
+  pos: usize,
+  /// The query as it is sent to the database.
+  text: String,
+  params: Vec<String>,
+  placeholders: Vec<usize>,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Tok> {
+    self.toks.get(self.pos)
+  }
+
+  fn next(&mut self) -> Option<Tok> {
+    let tok = self.toks.get(self.pos).cloned();
+    self.pos += 1;
+    tok
+  }
+
+  /// Where errors at the end of the query point.
+  fn end_span(&self) -> Span {
+    self.toks.last().map_or_else(Span::call_site, Tok::span)
+  }
+
+  fn error_here(&self, expected: &str) -> syn::Error {
+    match self.peek() {
+      Some(tok) => syn::Error::new(
+        tok.span(),
+        format!("expected {}, found {}", expected, tok.describe()),
+      ),
+      None => syn::Error::new(self.end_span(), format!("expected {} after this", expected)),
+    }
+  }
+
+  fn emit(&mut self, text: &str) {
+    if !(self.text.is_empty() || self.text.ends_with('(') || text == ")" || text == ",") {
+      self.text.push(' ');
+    }
+    self.text.push_str(text);
+  }
+
+  fn peek_keyword(&self, keyword: &str) -> bool {
+    matches!(self.peek(), Some(Tok::Word(word, _)) if word.eq_ignore_ascii_case(keyword))
+  }
+
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    if self.peek_keyword(keyword) {
+      self.pos += 1;
+      self.emit(keyword);
+      true
+    } else {
+      false
+    }
+  }
+
+  fn keyword(&mut self, keyword: &str) -> syn::Result<()> {
+    if self.eat_keyword(keyword) {
+      Ok(())
+    } else {
+      Err(self.error_here(keyword))
+    }
+  }
+
+  fn peek_sym(&self, sym: &str) -> bool {
+    matches!(self.peek(), Some(Tok::Sym(s, _)) if s == sym)
+  }
+
+  fn sym(&mut self, sym: &str) -> syn::Result<()> {
+    if self.peek_sym(sym) {
+      self.pos += 1;
+      self.emit(sym);
+      Ok(())
+    } else {
+      Err(self.error_here(&format!("`{}`", sym)))
+    }
+  }
+
+  fn open(&mut self) -> syn::Result<()> {
+    match self.peek() {
+      Some(Tok::Open(_)) => {
+        self.pos += 1;
+        self.emit("(");
+        Ok(())
+      }
+      _ => Err(self.error_here("`(`")),
+    }
+  }
+
+  fn close(&mut self) -> syn::Result<()> {
+    match self.peek() {
+      Some(Tok::Close(_)) => {
+        self.pos += 1;
+        self.emit(")");
+        Ok(())
+      }
+      _ => Err(self.error_here("`)`")),
+    }
+  }
+
+  /// A table or column name.
+  fn name(&mut self, what: &str) -> syn::Result<(String, Span)> {
+    match self.peek().cloned() {
+      Some(Tok::Word(word, span)) => {
+        if KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(&word)) {
+          return Err(syn::Error::new(
+            span,
+            format!(
+              "expected {}, found the keyword {}",
+              what,
+              word.to_uppercase()
+            ),
+          ));
+        }
+
+        self.pos += 1;
+        self.emit(&word);
+        Ok((word, span))
+      }
+      _ => Err(self.error_here(what)),
+    }
+  }
+
+  /// Comma separated names, at least one.
+  fn names(&mut self, what: &str) -> syn::Result<Vec<(String, Span)>> {
+    let mut names = vec![self.name(what)?];
+    while self.peek_sym(",") {
+      self.sym(",")?;
+      names.push(self.name(what)?);
+    }
+    Ok(names)
+  }
+
+  fn param(&mut self) -> syn::Result<()> {
+    self.pos += 1; // The `:`.
+
+    // :r#type is the parameter type, bound with bind_type.
+    let name = match self.next() {
+      Some(Tok::Word(word, _)) => word.trim_start_matches("r#").to_string(),
+      _ => {
+        self.pos -= 1;
+        return Err(self.error_here("a parameter name after `:`"));
+      }
+    };
+
+    let index = match self.params.iter().position(|param| *param == name) {
+      Some(index) => index,
+      None => {
+        self.params.push(name);
+        self.params.len() - 1
+      }
+    };
+
+    self.placeholders.push(index);
+    self.emit("?");
+    Ok(())
+  }
+
+  fn value(&mut self) -> syn::Result<()> {
+    match self.peek().cloned() {
+      Some(Tok::Sym(sym, _)) if sym == ":" => self.param(),
+      // A negative number.
+      Some(Tok::Sym(sym, _)) if sym == "-" => {
+        self.pos += 1;
+        match self.peek().cloned() {
+          Some(Tok::Lit(literal)) if is_number(&literal) => {
+            self.pos += 1;
+            self.emit(&format!("-{}", literal));
+            Ok(())
+          }
+          _ => Err(self.error_here("a number after `-`")),
+        }
+      }
+      Some(Tok::Lit(literal)) => {
+        self.pos += 1;
+        let text = sql_literal(&literal)?;
+        self.emit(&text);
+        Ok(())
+      }
+      Some(Tok::Word(word, _))
+        if ["NULL", "TRUE", "FALSE"]
+          .iter()
+          .any(|k| k.eq_ignore_ascii_case(&word)) =>
+      {
+        self.pos += 1;
+        self.emit(&word.to_uppercase());
+        Ok(())
+      }
+      Some(Tok::Word(..)) => self.name("a value").map(|_| ()),
+      _ => Err(self.error_here("a value")),
+    }
+  }
+
+  fn comparison(&mut self) -> syn::Result<()> {
+    if let Some(Tok::Open(_)) = self.peek() {
+      self.open()?;
+      self.condition()?;
+      return self.close();
+    }
+
+    self.value()?;
+
+    if self.eat_keyword("IS") {
+      self.eat_keyword("NOT");
+      return self.keyword("NULL");
+    }
+
+    for op in &["=", "!=", "<>", "<=", ">=", "<", ">"] {
+      if self.peek_sym(op) {
+        self.sym(op)?;
+        return self.value();
+      }
+    }
+
+    Err(self.error_here("a comparison"))
+  }
+
+  fn negation(&mut self) -> syn::Result<()> {
+    self.eat_keyword("NOT");
+    self.comparison()
+  }
+
+  fn conjunction(&mut self) -> syn::Result<()> {
+    self.negation()?;
+    while self.eat_keyword("AND") {
+      self.negation()?;
+    }
+    Ok(())
+  }
+
+  fn condition(&mut self) -> syn::Result<()> {
+    self.conjunction()?;
+    while self.eat_keyword("OR") {
+      self.conjunction()?;
+    }
+    Ok(())
+  }
+
+  fn where_clause(&mut self) -> syn::Result<()> {
+    if self.eat_keyword("WHERE") {
+      self.condition()?;
+    }
+    Ok(())
+  }
+
+  fn select(&mut self) -> syn::Result<String> {
+    if self.peek_sym("*") {
+      self.sym("*")?;
+    } else {
+      self.names("`*` or a column")?;
+    }
+
+    self.keyword("FROM")?;
+    let (table, _) = self.name("a table")?;
+    self.where_clause()?;
+
+    if self.eat_keyword("ORDER") {
+      self.keyword("BY")?;
+      loop {
+        self.name("a column")?;
+        if !self.eat_keyword("ASC") {
+          self.eat_keyword("DESC");
+        }
+        if !self.peek_sym(",") {
+          break;
+        }
+        self.sym(",")?;
+      }
+    }
+
+    if self.eat_keyword("LIMIT") {
+      match self.peek().cloned() {
+        Some(Tok::Sym(sym, _)) if sym == ":" => self.param()?,
+        Some(Tok::Lit(literal)) if literal.to_string().parse::<u64>().is_ok() => {
+          self.pos += 1;
+          self.emit(&literal.to_string());
+        }
+        _ => return Err(self.error_here("a row count or a parameter")),
+      }
+    }
+
+    Ok(table)
+  }
+
+  fn insert(&mut self) -> syn::Result<String> {
+    self.keyword("INTO")?;
+    let (table, _) = self.name("a table")?;
+
+    self.open()?;
+    let columns = self.names("a column")?;
+    self.close()?;
+
+    for (i, (column, span)) in columns.iter().enumerate() {
+      if columns[..i].iter().any(|(other, _)| other == column) {
+        return Err(syn::Error::new(
+          *span,
+          format!("{} is given more than once", column),
+        ));
+      }
+    }
+
+    self.keyword("VALUES")?;
+    let open = self.peek().map(Tok::span);
+    self.open()?;
+    let mut values = 1;
+    self.value()?;
+    while self.peek_sym(",") {
+      self.sym(",")?;
+      self.value()?;
+      values += 1;
+    }
+    self.close()?;
+
+    if values != columns.len() {
+      return Err(syn::Error::new(
+        open.unwrap(),
+        format!(
+          "{} columns but {} {}",
+          columns.len(),
+          values,
+          if values == 1 { "value" } else { "values" }
+        ),
+      ));
+    }
+
+    Ok(table)
+  }
+
+  fn update(&mut self) -> syn::Result<String> {
+    let (table, _) = self.name("a table")?;
+    self.keyword("SET")?;
+
+    loop {
+      self.name("a column")?;
+      self.sym("=")?;
+      self.value()?;
+      if !self.peek_sym(",") {
+        break;
+      }
+      self.sym(",")?;
+    }
+
+    self.where_clause()?;
+    Ok(table)
+  }
+
+  fn delete(&mut self) -> syn::Result<String> {
+    self.keyword("FROM")?;
+    let (table, _) = self.name("a table")?;
+    self.where_clause()?;
+    Ok(table)
+  }
+
+  fn statement(&mut self) -> syn::Result<(&'static str, String)> {
+    let (kind, table) = if self.eat_keyword("SELECT") {
+      ("Select", self.select()?)
+    } else if self.eat_keyword("INSERT") {
+      ("Insert", self.insert()?)
+    } else if self.eat_keyword("UPDATE") {
+      ("Update", self.update()?)
+    } else if self.eat_keyword("DELETE") {
+      ("Delete", self.delete()?)
+    } else {
+      return Err(self.error_here("SELECT, INSERT, UPDATE or DELETE"));
+    };
+
+    // A trailing ; is fine.
+    if self.peek_sym(";") {
+      self.pos += 1;
+    }
+
+    if let Some(tok) = self.peek() {
+      return Err(syn::Error::new(
+        tok.span(),
+        format!("unexpected {} after the end of the query", tok.describe()),
+      ));
+    }
+
+    Ok((kind, table))
+  }
+}
+
+fn is_number(literal: &Literal) -> bool {
+  let text = literal.to_string();
+  text.parse::<i64>().is_ok() || text.parse::<f64>().is_ok()
+}
+
+/// `literal` written the way SQL writes it.
+fn sql_literal(literal: &Literal) -> syn::Result<String> {
+  match syn::Lit::new(literal.clone()) {
+    syn::Lit::Str(s) => Ok(format!("'{}'", s.value().replace('\'', "''"))),
+    syn::Lit::Int(int) if int.suffix().is_empty() => Ok(int.base10_digits().to_string()),
+    syn::Lit::Float(float) if float.suffix().is_empty() => Ok(float.base10_digits().to_string()),
+    _ => Err(syn::Error::new(
+      literal.span(),
+      "expected a number or a \"string\"",
+    )),
+  }
+}
+
+fn expand(input: TokenStream2) -> syn::Result<TokenStream2> {
+  let mut toks = Vec::new();
+  flatten(input, &mut toks)?;
+
+  let mut parser = Parser {
+    toks,
+    pos: 0,
+    text: String::new(),
+    params: Vec::new(),
+    placeholders: Vec::new(),
+  };
+
+  let (kind, table) = parser.statement()?;
+  let kind = format_ident!("{}", kind);
+  let text = &parser.text;
+  let params = &parser.params;
+  let placeholders = &parser.placeholders;
+
+  let binds = params.iter().enumerate().map(|(index, param)| {
+    let method = format_ident!("bind_{}", param);
+    quote! {
+      pub fn #method(self, value: impl Into<::sql::Value>) -> Self {
+        SqlQuery(self.0.bind_index(#index, value.into()))
+      }
+    }
+  });
+
+  // A type of its own, so the bind methods match the parameters.
+  Ok(quote! {
+    {
+      struct SqlQuery(::sql::Query);
+
+      #[allow(dead_code)]
+      impl SqlQuery {
+        #(#binds)*
+
+        pub fn into_query(self) -> ::sql::Query {
+          self.0
+        }
+      }
+
+      impl ::std::ops::Deref for SqlQuery {
+        type Target = ::sql::Query;
+
+        fn deref(&self) -> &::sql::Query {
+          &self.0
+        }
+      }
+
+      SqlQuery(::sql::Query::new(
+        #text,
+        ::sql::Kind::#kind,
+        #table,
+        &[#(#params),*],
+        &[#(#placeholders),*],
+      ))
+    }
+  })
+}
+
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+  expand(input.into())
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
@@ -0,0 +1,191 @@
+// The runtime half of sql!, the macro itself lives in sql_macro.
+//
+// A proc-macro crate can only export macros, so the types the
+// expansion refers to are defined here and the macro is re-exported:
+//
+//   let query = sql!(SELECT title FROM posts WHERE id = :id LIMIT 1).bind_id(7);
+//
+//   query.text()      "SELECT title FROM posts WHERE id = ? LIMIT 1"
+//   query.values()    Ok(vec![Value::Integer(7)])
+//
+// Every :name in the query gets a bind_name method, so binding a
+// parameter the query doesn't have is a compile error.
+use std::{error::Error, fmt};
+
+pub use sql_macro::sql;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+  Select,
+  Insert,
+  Update,
+  Delete,
+}
+
+/// A value bound to a parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Null,
+  Integer(i64),
+  Real(f64),
+  Text(String),
+  Boolean(bool),
+}
+
+impl From<i64> for Value {
+  fn from(value: i64) -> Self {
+    Value::Integer(value)
+  }
+}
+
+impl From<i32> for Value {
+  fn from(value: i32) -> Self {
+    Value::Integer(value.into())
+  }
+}
+
+impl From<u32> for Value {
+  fn from(value: u32) -> Self {
+    Value::Integer(value.into())
+  }
+}
+
+impl From<f64> for Value {
+  fn from(value: f64) -> Self {
+    Value::Real(value)
+  }
+}
+
+impl From<bool> for Value {
+  fn from(value: bool) -> Self {
+    Value::Boolean(value)
+  }
+}
+
+impl From<&str> for Value {
+  fn from(value: &str) -> Self {
+    Value::Text(value.to_string())
+  }
+}
+
+impl From<String> for Value {
+  fn from(value: String) -> Self {
+    Value::Text(value)
+  }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+  fn from(value: Option<T>) -> Self {
+    value.map_or(Value::Null, Into::into)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindError {
+  /// The query has no parameter called that.
+  UnknownParam(String),
+  /// These parameters weren't bound.
+  Unbound(Vec<&'static str>),
+}
+
+impl fmt::Display for BindError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      BindError::UnknownParam(name) => write!(f, "the query has no parameter :{}", name),
+      BindError::Unbound(names) => write!(f, "unbound parameters: :{}", names.join(", :")),
+    }
+  }
+}
+
+impl Error for BindError {}
+
+/// A query checked by sql!, with `?` in place of its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+  text: &'static str,
+  kind: Kind,
+  table: &'static str,
+  /// Every parameter, once, in the order they first appear.
+  params: &'static [&'static str],
+  /// For each `?` of the text, the index of its parameter.
+  placeholders: &'static [usize],
+  values: Vec<Option<Value>>,
+}
+
+impl Query {
+  /// Used by sql!, which has already checked the query.
+  #[doc(hidden)]
+  pub fn new(
+    text: &'static str,
+    kind: Kind,
+    table: &'static str,
+    params: &'static [&'static str],
+    placeholders: &'static [usize],
+  ) -> Self {
+    Self {
+      text,
+      kind,
+      table,
+      params,
+      placeholders,
+      values: vec![None; params.len()],
+    }
+  }
+
+  pub fn text(&self) -> &'static str {
+    self.text
+  }
+
+  pub fn kind(&self) -> Kind {
+    self.kind
+  }
+
+  pub fn table(&self) -> &'static str {
+    self.table
+  }
+
+  pub fn params(&self) -> &'static [&'static str] {
+    self.params
+  }
+
+  /// Binds the parameter at `index` of params().
+  #[doc(hidden)]
+  pub fn bind_index(mut self, index: usize, value: Value) -> Self {
+    self.values[index] = Some(value);
+    self
+  }
+
+  /// Binds `name` when it is only known at runtime,
+  /// the bind_name methods check it at compile time instead.
+  pub fn bind(self, name: &str, value: impl Into<Value>) -> Result<Self, BindError> {
+    match self.params.iter().position(|param| *param == name) {
+      Some(index) => Ok(self.bind_index(index, value.into())),
+      None => Err(BindError::UnknownParam(name.to_string())),
+    }
+  }
+
+  /// The value of each `?` of the text, in order.
+  ///
+  /// A parameter used more than once is repeated.
+  pub fn values(&self) -> Result<Vec<Value>, BindError> {
+    let unbound: Vec<&'static str> = self
+      .params
+      .iter()
+      .zip(&self.values)
+      .filter(|(_, value)| value.is_none())
+      .map(|(param, _)| *param)
+      .collect();
+
+    if !unbound.is_empty() {
+      return Err(BindError::Unbound(unbound));
+    }
+
+    Ok(
+      self
+        .placeholders
+        .iter()
+        .map(|&index| self.values[index].clone().unwrap())
+        .collect(),
+    )
+  }
+}
@@ -0,0 +1,80 @@
+use sql::{sql, BindError, Kind, Value};
+
+#[test]
+fn select() {
+  let query = sql!(
+    select id, title from posts
+    where (author = :author or editor = :author) and not draft = true and deleted_at is null
+    order by published_at desc, id
+    limit 10
+  );
+
+  assert_eq!(
+    "SELECT id, title FROM posts \
+     WHERE (author = ? OR editor = ?) AND NOT draft = TRUE AND deleted_at IS NULL \
+     ORDER BY published_at DESC, id LIMIT 10",
+    query.text()
+  );
+  assert_eq!(Kind::Select, query.kind());
+  assert_eq!("posts", query.table());
+  assert_eq!(&["author"], query.params());
+
+  let query = query.bind_author("amy");
+  assert_eq!(
+    Ok(vec![Value::from("amy"), Value::from("amy")]),
+    query.values()
+  );
+}
+
+#[test]
+fn insert_update_delete() {
+  let insert = sql!(INSERT INTO posts (title, score, body) VALUES (:title, -1.5, "it's here"))
+    .bind_title("Salad");
+  assert_eq!(
+    "INSERT INTO posts (title, score, body) VALUES (?, -1.5, 'it''s here')",
+    insert.text()
+  );
+  assert_eq!(Kind::Insert, insert.kind());
+  assert_eq!(Ok(vec![Value::Text("Salad".to_string())]), insert.values());
+
+  let update = sql!(UPDATE posts SET title = :title, views = 0 WHERE id = :id)
+    .bind_id(3)
+    .bind_title(None::<String>);
+  assert_eq!(
+    "UPDATE posts SET title = ?, views = 0 WHERE id = ?",
+    update.text()
+  );
+  assert_eq!(Ok(vec![Value::Null, Value::Integer(3)]), update.values());
+
+  let delete = sql!(DELETE FROM posts WHERE views < :min;);
+  assert_eq!("DELETE FROM posts WHERE views < ?", delete.text());
+  assert_eq!(Kind::Delete, delete.kind());
+}
+
+#[test]
+fn reports_unbound_params() {
+  let query = sql!(SELECT * FROM posts WHERE id = :id LIMIT :limit);
+
+  assert_eq!(Err(BindError::Unbound(vec!["id", "limit"])), query.values());
+
+  let query = query.into_query().bind("limit", 5).unwrap();
+  assert_eq!(Err(BindError::Unbound(vec!["id"])), query.values());
+  assert_eq!(
+    Err(BindError::UnknownParam("offset".to_string())),
+    query.bind("offset", 1).map(|_| ())
+  );
+}
+
+#[test]
+fn raw_identifiers_as_params() {
+  let query = sql!(SELECT * FROM posts WHERE kind = :r#type).bind_type("poem");
+
+  assert_eq!(&["type"], query.params());
+  assert_eq!(Ok(vec![Value::Text("poem".to_string())]), query.values());
+}
+
+#[test]
+fn ui() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,5 @@
+use sql::sql;
+
+fn main() {
+  sql!(SELECT id FROM posts WHERE order = 1);
+}
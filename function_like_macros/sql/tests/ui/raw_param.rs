@@ -0,0 +1,6 @@
+use sql::sql;
+
+fn main() {
+  // The r# is only there to write the keyword, it isn't part of the name.
+  sql!(SELECT * FROM posts WHERE kind = :r#type).bind_r_type("poem");
+}
@@ -0,0 +1,5 @@
+use sql::sql;
+
+fn main() {
+  sql!(DELETE FROM posts WHERE id =);
+}
@@ -0,0 +1,6 @@
+use sql::sql;
+
+fn main() {
+  // Only the parameters of the query can be bound.
+  sql!(SELECT * FROM posts WHERE id = :id).bind_title("Salad");
+}
@@ -0,0 +1,5 @@
+use sql::sql;
+
+fn main() {
+  sql!(INSERT INTO posts (title, body) VALUES (:title));
+}
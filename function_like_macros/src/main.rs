@@ -1,3 +1,5 @@
+use sql::sql;
+
 /// Function-like macros
 ///
 /// Function-like macros define macros that look like function calls.
@@ -26,7 +28,20 @@
 /// The macro receiver that is between the parethenses when the macro
 /// is called sql!(...) and returns a TokenStream to geneate
 /// the desired code.
-
+///
+/// The sql crate next to this one implements it for a subset of SQL.
 fn main() {
-  println!("Hello, world!");
+  let sql = sql!(SELECT * FROM posts WHERE id = 1);
+  println!("{}", sql.text());
+
+  // Parameters become placeholders, and each gets a bind method.
+  let sql = sql!(SELECT title FROM posts WHERE author = :author ORDER BY id DESC LIMIT 5)
+    .bind_author("amy");
+  println!("{} {:?}", sql.text(), sql.values());
+
+  // Misspelling a keyword, as in
+  //
+  //   sql!(SELECT * FORM posts)
+  //
+  // fails to compile with "expected FROM, found `FORM`".
 }
@@ -1,81 +1,1130 @@
-// Error is a trait object.
+// run used to return Box<dyn Error>, a trait object that can
+// hold any type implementing the Error trait. That is flexible,
+// but the caller can only print it, it can't tell what went wrong.
 //
-// We will return a type that implements the Error trait
-// when something goes wrong. This way we don't need to specify
-// what particular type the return value will be, this gives
-// us flexibility to return error values that may be of different
-// types in different errors cases.
-//
-//// The dyn keyword is short for dynamic.
-use std::{env, error::Error, fs};
+// Now run returns our own Error enum, so callers can match
+// on its variants and decide what to do with each one.
+use std::{
+  cmp::Reverse,
+  env, error, fmt,
+  fs::{self, File},
+  io::{self, BufRead, BufReader, Read, Write},
+  path::{Path, PathBuf},
+  sync::{mpsc, Arc},
+  thread,
+};
+
+mod aho_corasick;
+mod args;
+mod case;
+mod color;
+mod decompress;
+mod glob;
+mod json;
+mod pool;
+mod regex;
+mod replace;
+mod searcher;
+mod walk;
+
+use aho_corasick::AhoCorasick;
+use args::Arg;
+use color::{ColorChoice, Colors};
+use glob::Glob;
+use json::Stats;
+use pool::ThreadPool;
+pub use regex::{Bounds, Captures, Regex, RegexError};
+use replace::Replace;
+pub use replace::Template;
+pub use searcher::{Line, LineKind, SearchOptions, Searcher};
+use walk::{Filter, STDIN};
+
+/// How many bytes are looked at to decide whether a file is binary.
+const BINARY_CHECK_LEN: usize = 8 * 1024;
 
 // We are using 'a here because the data returned
 // by this function will live as long as contents.
-fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
   contents
     .lines()
     .filter(|&line| line.contains(query))
     .collect()
 }
 
-fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-  let query = query.to_lowercase();
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+  // Folding one character at a time, instead of lowercasing
+  // every line into a new String, doesn't allocate at all.
+  contents
+    .lines()
+    .filter(|&line| case::find(line, query, 0).is_some())
+    .collect()
+}
 
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<&'a str> {
   contents
     .lines()
-    .filter(|&line| line.to_lowercase().contains(&query))
+    .filter(|&line| regex.is_match(line))
     .collect()
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-  let contents = fs::read_to_string(config.filename)?;
+#[derive(Debug)]
+pub enum Pattern {
+  // --fixed-strings: the query is searched for as is.
+  Fixed {
+    query: String,
+    case_insensitive: bool,
+  },
+  // Several fixed strings, searched for in a single pass.
+  Literals(AhoCorasick),
+  // The query compiled once, before any line is searched.
+  Regex(Regex),
+  // Several queries, at least one of them a regular expression.
+  Regexes(Vec<Regex>),
+}
 
-  let lines = if config.case_insensitive {
-    search_case_insensitive(&config.query, &contents)
-  } else {
-    search(&config.query, &contents)
+impl Pattern {
+  /// Picks the fastest way to search for every one of `queries`.
+  ///
+  /// Queries without regular expression syntax are searched for
+  /// as fixed strings even without --fixed-strings.
+  pub fn new(
+    mut queries: Vec<String>,
+    fixed_strings: bool,
+    case_insensitive: bool,
+    bounds: Bounds,
+  ) -> Result<Self, RegexError> {
+    let literals = fixed_strings
+      || queries
+        .iter()
+        .all(|query| !query.contains(|c| "\\.+*?()|[]{}^$".contains(c)));
+
+    // Searching for literals can't tell where words and lines
+    // end, with -w and -x the queries become regular expressions.
+    let anywhere = bounds == Bounds::Anywhere;
+
+    if fixed_strings && !anywhere {
+      queries = queries.iter().map(|query| regex::escape(query)).collect();
+    }
+
+    let pattern = match queries.len() {
+      // No query at all, as with an empty -f file: nothing matches.
+      0 => Pattern::Regexes(Vec::new()),
+      1 if fixed_strings && anywhere => Pattern::Fixed {
+        query: queries.remove(0),
+        case_insensitive,
+      },
+      1 => Pattern::Regex(Regex::with_bounds(&queries[0], case_insensitive, bounds)?),
+      _ if literals && anywhere => Pattern::Literals(AhoCorasick::new(&queries, case_insensitive)),
+      _ => Pattern::Regexes(
+        queries
+          .iter()
+          .map(|query| Regex::with_bounds(query, case_insensitive, bounds))
+          .collect::<Result<_, _>>()?,
+      ),
+    };
+
+    Ok(pattern)
+  }
+
+  pub fn is_match(&self, line: &str) -> bool {
+    self.find(line).is_some()
+  }
+
+  /// Returns the leftmost match in `line` at or after `start`,
+  /// the longest one when several queries match there.
+  pub fn captures_at(&self, line: &str, start: usize) -> Option<Captures> {
+    match self {
+      Pattern::Fixed {
+        query,
+        case_insensitive,
+      } => find_fixed(line, query, start, *case_insensitive)
+        .map(|(start, end)| Captures::whole(start, end)),
+      Pattern::Literals(automaton) => automaton
+        .find_at(line, start)
+        .map(|m| Captures::whole(m.start, m.end)),
+      Pattern::Regex(regex) => regex.captures_at(line, start),
+      Pattern::Regexes(regexes) => regexes
+        .iter()
+        .filter_map(|regex| regex.captures_at(line, start))
+        .min_by_key(|captures| captures.get(0).map(|(start, end)| (start, Reverse(end)))),
+    }
+  }
+
+  /// Returns every non overlapping match in `line`, left to right.
+  pub fn captures_iter<'p, 't>(&'p self, line: &'t str) -> CapturesIter<'p, 't> {
+    CapturesIter {
+      pattern: self,
+      line,
+      start: Some(0),
+    }
+  }
+
+  /// Returns the byte range of every match in `line`.
+  pub fn matches<'p, 't>(&'p self, line: &'t str) -> impl Iterator<Item = (usize, usize)> + 'p
+  where
+    't: 'p,
+  {
+    self
+      .captures_iter(line)
+      .filter_map(|captures| captures.get(0))
+  }
+
+  /// Returns the index of the query that matches `line`.
+  ///
+  /// When more than one does, the first one found wins.
+  pub fn find(&self, line: &str) -> Option<usize> {
+    let matched = match self {
+      Pattern::Fixed {
+        query,
+        case_insensitive,
+      } => find_fixed(line, query, 0, *case_insensitive).is_some(),
+      Pattern::Literals(automaton) => return automaton.find_at(line, 0).map(|m| m.pattern),
+      Pattern::Regex(regex) => regex.is_match(line),
+      Pattern::Regexes(regexes) => return regexes.iter().position(|regex| regex.is_match(line)),
+    };
+
+    if matched {
+      Some(0)
+    } else {
+      None
+    }
+  }
+}
+
+/// Iterator returned by [Pattern::captures_iter].
+pub struct CapturesIter<'p, 't> {
+  pattern: &'p Pattern,
+  line: &'t str,
+  // Where the next search starts, None once the line is done.
+  start: Option<usize>,
+}
+
+impl<'p, 't> Iterator for CapturesIter<'p, 't> {
+  type Item = Captures;
+
+  fn next(&mut self) -> Option<Captures> {
+    let captures = self.pattern.captures_at(self.line, self.start?)?;
+    let (start, end) = captures.get(0)?;
+
+    // An empty match would be found again at the same position,
+    // so the next search starts one character later.
+    self.start = if end > start {
+      Some(end)
+    } else {
+      self.line[end..].chars().next().map(|c| end + c.len_utf8())
+    };
+
+    Some(captures)
+  }
+}
+
+/// Returns the byte range of the first occurrence of `query` in `line`
+/// at or after `start`.
+fn find_fixed(
+  line: &str,
+  query: &str,
+  start: usize,
+  case_insensitive: bool,
+) -> Option<(usize, usize)> {
+  if case_insensitive {
+    return case::find(line, query, start);
+  }
+
+  line[start..]
+    .find(query)
+    .map(|i| (start + i, start + i + query.len()))
+}
+
+/// Returns the selected lines of `contents`, in order, together with
+/// the context lines around them.
+pub fn search_lines<'a>(
+  pattern: &Pattern,
+  contents: &'a str,
+  options: &SearchOptions,
+) -> Vec<Line<'a>> {
+  let mut lines = Vec::new();
+
+  Searcher::new(pattern, options)
+    .search_slice(contents.as_bytes(), &mut |line| {
+      lines.push(line);
+      Ok(())
+    })
+    // Pushing to a Vec can't fail.
+    .unwrap();
+
+  lines
+}
+
+// Binary files are skipped, like system grep we assume a file
+// with a NUL byte near the start is not text.
+fn is_binary(contents: &[u8]) -> bool {
+  contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+/// How `run` prints what it finds.
+#[derive(Debug, Default)]
+struct Output {
+  /// -n: prefix lines with their line number.
+  line_number: bool,
+  /// -c: print how many lines were selected instead of the lines.
+  count: bool,
+  /// -l: only print the names of files with selected lines.
+  files_with_matches: bool,
+  /// -L: only print the names of files without selected lines.
+  files_without_match: bool,
+  /// --show-pattern: prefix selected lines with the query that matched them.
+  show_pattern: bool,
+  /// --json: print JSON Lines records instead, see the json module.
+  json: bool,
+  /// --color: None when the output isn't colored.
+  colors: Option<Colors>,
+}
+
+impl Output {
+  /// False when only counts, file names or JSON records are printed.
+  fn prints_lines(&self) -> bool {
+    !(self.count || self.files_with_matches || self.files_without_match || self.json)
+  }
+}
+
+/// Errors that make `run` skip a file or stop altogether.
+#[derive(Debug)]
+pub enum Error {
+  /// A file or directory couldn't be read, the other ones are still searched.
+  Path { path: PathBuf, error: io::Error },
+  /// Writing the results failed, so nothing else can be printed.
+  Output(io::Error),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Path { path, error } => write!(f, "{}: {}", path.display(), error),
+      Error::Output(error) => write!(f, "can't write the results: {}", error),
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      Error::Path { error, .. } | Error::Output(error) => Some(error),
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(error: io::Error) -> Self {
+    Error::Output(error)
+  }
+}
+
+/// What `run` found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outcome {
+  /// Whether any line was selected.
+  pub matched: bool,
+  /// How many files or directories couldn't be read.
+  pub errors: usize,
+}
+
+impl Outcome {
+  /// The exit status of system grep: 0 when a line was selected,
+  /// 1 when none was and 2 when something went wrong.
+  pub fn exit_code(&self) -> i32 {
+    if self.errors > 0 {
+      2
+    } else if self.matched {
+      0
+    } else {
+      1
+    }
+  }
+}
+
+/// Reports an error that only affects one file.
+fn report(error: &Error) {
+  eprintln!("grep: {}", error);
+}
+
+pub fn run(config: Config) -> Result<Outcome, Error> {
+  let (mut files, walk_errors) = walk::files(&config.paths, &config.filter);
+
+  for error in &walk_errors {
+    report(error);
+  }
+  let mut errors = walk_errors.len();
+
+  if config.sort {
+    files.sort();
+  }
+
+  // Like system grep, lines are prefixed with the path
+  // as soon as more than one file could be searched.
+  let with_path =
+    config.paths.len() > 1 || config.paths.iter().any(|path| Path::new(path).is_dir());
+
+  let separate_files = config.output.prints_lines() && config.search.has_context();
+
+  // Jobs run on other threads and may outlive this function's borrows.
+  let config = Arc::new(config);
+
+  let mut out = FileOutput {
+    stdout: io::stdout().lock(),
+    separator: separate_files.then_some(&config.output.colors),
+    printed_any: false,
+    file_started: false,
+    write_failed: false,
   };
 
-  for line in lines {
-    println!("{}", line);
+  let mut stats = Stats::default();
+
+  // Searched one after the other there's nothing to keep apart,
+  // so lines are printed as soon as they are found, even when
+  // stdin only sends more once it gets an answer.
+  if files.len() <= 1 || config.threads == 1 {
+    for file in files {
+      out.start_file();
+
+      let mut printer = Printer::new(&config, with_path, &mut out);
+      let result = search_file(&config, &mut printer, &file);
+      stats.add(printer.stats);
+
+      match result {
+        Ok(()) => {}
+        Err(error) if out.write_failed => return Err(error.into()),
+        Err(error) => {
+          report(&Error::Path { path: file, error });
+          errors += 1;
+        }
+      }
+    }
+
+    return finish(&config, &mut out, stats, errors);
+  }
+
+  let pool = ThreadPool::new(config.threads);
+  let mut receivers = Vec::with_capacity(files.len());
+
+  for file in files {
+    let config = Arc::clone(&config);
+    // Bounded, so a job that gets ahead of the printing waits
+    // instead of holding the output of the whole file.
+    let (sender, receiver) = mpsc::sync_channel(CHUNKS_IN_FLIGHT);
+    receivers.push(receiver);
+
+    pool.execute(move || {
+      let mut printer = Printer::new(&config, with_path, Chunks::new(sender));
+
+      let result = search_file(&config, &mut printer, &file)
+        .and_then(|()| printer.out.flush())
+        .map(|()| printer.stats)
+        .map_err(|error| Error::Path { path: file, error });
+
+      // The receiver is only gone if run already returned an error.
+      let _ = printer.out.sender.send(Chunk::Done(result));
+    });
+  }
+
+  // Files are printed whole and in order, one at a time, while
+  // the ones after them are already being searched.
+  for receiver in receivers {
+    out.start_file();
+
+    for chunk in receiver {
+      match chunk {
+        Chunk::Output(output) => out.write_all(&output)?,
+        Chunk::Done(Ok(file_stats)) => stats.add(file_stats),
+        Chunk::Done(Err(error)) => {
+          report(&error);
+          errors += 1;
+        }
+      }
+    }
+  }
+
+  finish(&config, &mut out, stats, errors)
+}
+
+fn finish(
+  config: &Config,
+  out: &mut FileOutput,
+  stats: Stats,
+  errors: usize,
+) -> Result<Outcome, Error> {
+  if config.output.json {
+    json::summary(&mut out.stdout, stats)?;
+  }
+
+  Ok(Outcome {
+    matched: stats.searches_with_match > 0,
+    errors,
+  })
+}
+
+/// Writes the output of each file to stdout, after a -- separator
+/// when lines of an earlier file were printed too.
+struct FileOutput<'c> {
+  stdout: io::StdoutLock<'static>,
+  // The colors of the separator, None when files aren't separated.
+  separator: Option<&'c Option<Colors>>,
+  printed_any: bool,
+  file_started: bool,
+  // Tells errors writing to stdout apart from errors reading a file.
+  write_failed: bool,
+}
+
+impl FileOutput<'_> {
+  fn start_file(&mut self) {
+    self.file_started = false;
+  }
+}
+
+impl Write for FileOutput<'_> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if !buf.is_empty() && !self.file_started {
+      if let (Some(colors), true) = (self.separator, self.printed_any) {
+        match colors {
+          Some(colors) => color::paint(&mut self.stdout, &colors.separator, "--")?,
+          None => write!(self.stdout, "--")?,
+        }
+        writeln!(self.stdout)?;
+      }
+
+      self.printed_any = true;
+      self.file_started = true;
+    }
+
+    let result = self.stdout.write(buf);
+    self.write_failed |= result.is_err();
+    result
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    let result = self.stdout.flush();
+    self.write_failed |= result.is_err();
+    result
+  }
+}
+
+/// How much a job prints before handing it over to be written.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many chunks of a file can wait to be written.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+enum Chunk {
+  Output(Vec<u8>),
+  /// The file was searched, nothing else comes after this.
+  Done(Result<Stats, Error>),
+}
+
+/// Collects what a job prints and sends it to `run` in chunks.
+struct Chunks {
+  buffer: Vec<u8>,
+  sender: mpsc::SyncSender<Chunk>,
+}
+
+impl Chunks {
+  fn new(sender: mpsc::SyncSender<Chunk>) -> Self {
+    Self {
+      buffer: Vec::new(),
+      sender,
+    }
+  }
+}
+
+impl Write for Chunks {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.buffer.extend_from_slice(buf);
+
+    if self.buffer.len() >= CHUNK_SIZE {
+      self.flush()?;
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    if self.buffer.is_empty() {
+      return Ok(());
+    }
+
+    let chunk = Chunk::Output(std::mem::take(&mut self.buffer));
+
+    // run stopped listening, because writing to stdout failed.
+    self
+      .sender
+      .send(chunk)
+      .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+  }
+}
+
+fn search_file(config: &Config, printer: &mut Printer<impl Write>, file: &Path) -> io::Result<()> {
+  if let Some(replace) = &config.replace {
+    return replace_file(config, replace, printer, file);
+  }
+
+  if file == Path::new(STDIN) {
+    let stdin = io::stdin();
+    let name = "(standard input)";
+
+    if config.decompress {
+      return search_compressed(config, printer, name, stdin.lock());
+    }
+
+    return search_input(config, printer, name, stdin.lock());
+  }
+
+  let name = file.display().to_string();
+  let handle = File::open(file)?;
+
+  if config.decompress {
+    return search_compressed(config, printer, &name, BufReader::new(handle));
+  }
+
+  match searcher::mmap(&handle) {
+    Some(contents) => {
+      if is_binary(&contents) {
+        return Ok(());
+      }
+
+      let searcher = Searcher::new(&config.pattern, &config.search);
+      printer.search(&name, |sink| searcher.search_slice(&contents, sink))
+    }
+    None => search_input(config, printer, &name, BufReader::new(handle)),
+  }
+}
+
+/// Rewrites the matches in `file`, printing a diff of the
+/// changes unless they are written back with --in-place.
+///
+/// Stdin can't be written back, so its changes are always printed.
+fn replace_file(
+  config: &Config,
+  replace: &Replace,
+  printer: &mut Printer<impl Write>,
+  file: &Path,
+) -> io::Result<()> {
+  let stdin = file == Path::new(STDIN);
+
+  let mut contents = Vec::new();
+  if stdin {
+    io::stdin().lock().read_to_end(&mut contents)?;
+  } else {
+    contents = fs::read(file)?;
+  }
+
+  if is_binary(&contents) {
+    return Ok(());
   }
 
-  Ok(())
+  // Lossy decoding would write the replacement characters back.
+  let contents = String::from_utf8(contents).map_err(|_| {
+    io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("{} is not valid UTF-8", file.display()),
+    )
+  })?;
+
+  let replaced = replace::replace_all(&config.pattern, &contents, &replace.template);
+
+  let changed = contents
+    .lines()
+    .zip(replaced.lines())
+    .filter(|(old, new)| old != new)
+    .count();
+
+  printer.stats.add(Stats {
+    searches: 1,
+    searches_with_match: (changed > 0) as usize,
+    matched_lines: changed,
+  });
+
+  if changed == 0 {
+    return Ok(());
+  }
+
+  if replace.in_place && !stdin {
+    replace::write_in_place(file, &replaced, replace.backup.as_deref())
+  } else {
+    let name = if stdin {
+      "(standard input)".to_string()
+    } else {
+      file.display().to_string()
+    };
+
+    printer
+      .out
+      .write_all(replace::unified_diff(&name, &contents, &replaced).as_bytes())
+  }
+}
+
+/// -z: searches what `reader` decompresses to.
+///
+/// Like any other file that can't be read, an archive that can't be
+/// decompressed is reported by `run` and the rest are still searched.
+fn search_compressed(
+  config: &Config,
+  printer: &mut Printer<impl Write>,
+  name: &str,
+  reader: impl BufRead,
+) -> io::Result<()> {
+  search_input(config, printer, name, decompress::reader(reader)?)
+}
+
+fn search_input(
+  config: &Config,
+  printer: &mut Printer<impl Write>,
+  name: &str,
+  mut reader: impl BufRead,
+) -> io::Result<()> {
+  // Only the first chunk is looked at, like with mapped files.
+  if is_binary(reader.fill_buf()?) {
+    return Ok(());
+  }
+
+  let searcher = Searcher::new(&config.pattern, &config.search);
+  printer.search(name, |sink| searcher.search_reader(reader, sink))
+}
+
+/// Writes what is found in one file.
+struct Printer<'c, W> {
+  out: W,
+  output: &'c Output,
+  pattern: &'c Pattern,
+  queries: &'c [String],
+  with_path: bool,
+  with_context: bool,
+  // What was found in the files searched with this printer.
+  stats: Stats,
+}
+
+impl<'c, W: Write> Printer<'c, W> {
+  fn new(config: &'c Config, with_path: bool, out: W) -> Self {
+    Self {
+      out,
+      output: &config.output,
+      pattern: &config.pattern,
+      queries: &config.queries,
+      with_path,
+      with_context: config.search.has_context(),
+      stats: Stats::default(),
+    }
+  }
+
+  /// Runs `search`, printing what it finds in `name` as it goes.
+  fn search<'a>(
+    &mut self,
+    name: &str,
+    search: impl FnOnce(&mut dyn FnMut(Line<'a>) -> io::Result<()>) -> io::Result<()>,
+  ) -> io::Result<()> {
+    if self.output.json {
+      return self.search_json(name, search);
+    }
+
+    let print_lines = self.output.prints_lines();
+
+    let mut selected = 0;
+    let mut last_printed: Option<usize> = None;
+
+    search(&mut |line| {
+      if line.kind == LineKind::Match {
+        selected += 1;
+      }
+
+      if print_lines {
+        self.print_line(name, &line, last_printed)?;
+        last_printed = Some(line.number);
+      }
+
+      Ok(())
+    })?;
+
+    self.stats.add(Stats {
+      searches: 1,
+      searches_with_match: (selected > 0) as usize,
+      matched_lines: selected,
+    });
+
+    if self.output.files_with_matches || self.output.files_without_match {
+      if (selected > 0) == self.output.files_with_matches {
+        self.paint(|colors| &colors.path, name)?;
+        writeln!(self.out)?;
+      }
+    } else if self.output.count {
+      if self.with_path {
+        self.paint(|colors| &colors.path, name)?;
+        self.paint(|colors| &colors.separator, ":")?;
+      }
+      writeln!(self.out, "{}", selected)?;
+    }
+
+    Ok(())
+  }
+
+  /// Same as [Printer::search] but prints JSON records.
+  fn search_json<'a>(
+    &mut self,
+    name: &str,
+    search: impl FnOnce(&mut dyn FnMut(Line<'a>) -> io::Result<()>) -> io::Result<()>,
+  ) -> io::Result<()> {
+    let mut stats = Stats {
+      searches: 1,
+      ..Stats::default()
+    };
+
+    search(&mut |line| {
+      // Files without selected lines get no records at all.
+      if stats.searches_with_match == 0 {
+        json::begin(&mut self.out, name)?;
+        stats.searches_with_match = 1;
+      }
+
+      if line.kind == LineKind::Match {
+        stats.matched_lines += 1;
+      }
+
+      json::line(&mut self.out, name, &line, self.pattern)
+    })?;
+
+    if stats.searches_with_match > 0 {
+      json::end(&mut self.out, name, stats)?;
+    }
+
+    self.stats.add(stats);
+    Ok(())
+  }
+
+  /// Writes `text` in the color `pick` chooses, or as is without colors.
+  fn paint(&mut self, pick: impl Fn(&Colors) -> &String, text: &str) -> io::Result<()> {
+    match &self.output.colors {
+      Some(colors) => color::paint(&mut self.out, pick(colors), text),
+      None => write!(self.out, "{}", text),
+    }
+  }
+
+  fn print_line(&mut self, name: &str, line: &Line, last_printed: Option<usize>) -> io::Result<()> {
+    if self.with_context && last_printed.is_some_and(|last| line.number != last + 1) {
+      self.paint(|colors| &colors.separator, "--")?;
+      writeln!(self.out)?;
+    }
+
+    // Selected lines use : as separator, context lines use -.
+    let separator = match line.kind {
+      LineKind::Match => ":",
+      LineKind::Context => "-",
+    };
+
+    if self.with_path {
+      self.paint(|colors| &colors.path, name)?;
+      self.paint(|colors| &colors.separator, separator)?;
+    }
+
+    if self.output.line_number {
+      self.paint(|colors| &colors.line_number, &line.number.to_string())?;
+      self.paint(|colors| &colors.separator, separator)?;
+    }
+
+    if self.output.show_pattern {
+      if let Some(pattern) = line.pattern {
+        write!(self.out, "{}", self.queries[pattern])?;
+        self.paint(|colors| &colors.separator, separator)?;
+      }
+    }
+
+    // Only the matches in selected lines are highlighted,
+    // the lines selected by -v have none.
+    if self.output.colors.is_some() && line.kind == LineKind::Match {
+      let mut written = 0;
+
+      for (start, end) in self.pattern.matches(&line.text) {
+        if start == end {
+          continue;
+        }
+
+        write!(self.out, "{}", &line.text[written..start])?;
+        self.paint(|colors| &colors.matched, &line.text[start..end])?;
+        written = end;
+      }
+
+      writeln!(self.out, "{}", &line.text[written..])
+    } else {
+      writeln!(self.out, "{}", line.text)
+    }
+  }
 }
 
 #[derive(Debug)]
 pub enum ConfigError {
-  NotEnoughArguments { expected: usize, got: usize },
+  NotEnoughArguments {
+    expected: usize,
+    got: usize,
+  },
+  MissingValue {
+    flag: String,
+  },
+  InvalidNumber {
+    flag: String,
+    value: String,
+  },
+  InvalidPattern(RegexError),
+  PatternFile {
+    path: String,
+    error: io::Error,
+  },
+  InvalidGlob {
+    glob: String,
+    error: RegexError,
+  },
+  InvalidColor {
+    value: String,
+  },
+  /// A --replace template with a newline, which would split lines.
+  TemplateNewline {
+    template: String,
+  },
+  UnknownFlag {
+    flag: String,
+  },
+  UnexpectedValue {
+    flag: String,
+  },
+  /// --help was given, the caller should print [help] and exit.
+  Help,
+  /// --version was given.
+  Version,
+}
+
+/// The text printed by --help.
+pub fn help() -> String {
+  args::help()
+}
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Case {
+  Sensitive,
+  Insensitive,
+  /// Insensitive unless a query has an uppercase letter.
+  Smart,
+}
+
+/// True if `query` has an uppercase letter that isn't part of
+/// an escape like \W, which stands for a class and not a letter.
+fn has_uppercase(query: &str) -> bool {
+  let mut chars = query.chars();
+
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      chars.next();
+    } else if c.is_uppercase() {
+      return true;
+    }
+  }
+
+  false
 }
 
 #[derive(Debug)]
 pub struct Config {
-  query: String,
-  filename: String,
-  case_insensitive: bool,
+  pattern: Pattern,
+  // The queries the pattern was built from, for --show-pattern.
+  queries: Vec<String>,
+  paths: Vec<String>,
+  filter: Filter,
+  search: SearchOptions,
+  output: Output,
+  /// -j: how many files are searched at once.
+  threads: usize,
+  /// --sort: print files in path order instead of the order they are found in.
+  sort: bool,
+  /// --replace: rewrite the matches instead of printing them.
+  replace: Option<Replace>,
+  /// -z: search the contents of compressed files.
+  decompress: bool,
 }
 
 impl Config {
   pub fn new(args: &[String]) -> Result<Self, ConfigError> {
-    if args.len() < 3 {
-      return Err(ConfigError::NotEnoughArguments {
-        expected: 2,
-        got: args.len() - 1,
-      });
+    let mut fixed_strings = false;
+    let mut queries = Vec::new();
+    // Whether -e or -f was given, even if -f read no patterns.
+    let mut queries_given = false;
+    let mut filter = Filter::default();
+    let mut search = SearchOptions::default();
+    let mut output = Output::default();
+    let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut sort = false;
+    let mut template = None;
+    let mut in_place = false;
+    let mut backup = None;
+    let mut color = ColorChoice::Auto;
+    let mut bounds = Bounds::Anywhere;
+    let mut decompress = false;
+    let mut positional = Vec::new();
+
+    // CASE_INSENSITIVE only sets the default, -i, -s and -S override it.
+    let mut case = if env::var("CASE_INSENSITIVE").is_ok() {
+      Case::Insensitive
+    } else {
+      Case::Sensitive
+    };
+
+    for arg in args::parse(&args[1..])? {
+      let (name, value) = match arg {
+        Arg::Positional(arg) => {
+          positional.push(arg);
+          continue;
+        }
+        Arg::Flag(name) => (name, String::new()),
+        Arg::Value(name, value) => (name, value),
+      };
+
+      let number = || -> Result<usize, ConfigError> {
+        value.parse().map_err(|_| ConfigError::InvalidNumber {
+          flag: format!("--{}", name),
+          value: value.clone(),
+        })
+      };
+
+      match name {
+        "regexp" => {
+          queries_given = true;
+          queries.push(value);
+        }
+        "file" => {
+          queries_given = true;
+
+          let contents = fs::read_to_string(&value).map_err(|error| ConfigError::PatternFile {
+            path: value.clone(),
+            error,
+          })?;
+
+          queries.extend(contents.lines().map(String::from));
+        }
+        "fixed-strings" => fixed_strings = true,
+        "ignore-case" => case = Case::Insensitive,
+        "case-sensitive" => case = Case::Sensitive,
+        "smart-case" => case = Case::Smart,
+        // Like system grep, -x wins over -w.
+        "word-regexp" if bounds != Bounds::Line => bounds = Bounds::Word,
+        "word-regexp" => {}
+        "line-regexp" => bounds = Bounds::Line,
+        "invert-match" => search.invert = true,
+        "line-number" => output.line_number = true,
+        "count" => output.count = true,
+        "files-with-matches" => output.files_with_matches = true,
+        "files-without-match" => output.files_without_match = true,
+        "after-context" => search.after = number()?,
+        "before-context" => search.before = number()?,
+        "context" => {
+          search.after = number()?;
+          search.before = search.after;
+        }
+        "max-count" => search.max_count = Some(number()?),
+        "include" => filter.include.push(parse_glob(&value)?),
+        "exclude" => filter.exclude.push(parse_glob(&value)?),
+        "dereference-recursive" => filter.follow_links = true,
+        "threads" => {
+          threads = number()?;
+
+          if threads == 0 {
+            return Err(ConfigError::InvalidNumber {
+              flag: "--threads".to_string(),
+              value,
+            });
+          }
+        }
+        "sort" => sort = true,
+        "search-zip" => decompress = true,
+        "show-pattern" => output.show_pattern = true,
+        "color" => {
+          color = ColorChoice::parse(&value).ok_or(ConfigError::InvalidColor { value })?;
+        }
+        "json" => output.json = true,
+        "replace" => {
+          // The diff and the line counts rely on lines staying lines.
+          if value.contains('\n') {
+            return Err(ConfigError::TemplateNewline { template: value });
+          }
+
+          template = Some(Template::parse(&value));
+        }
+        "in-place" => in_place = true,
+        "backup" => backup = Some(value),
+        "help" => return Err(ConfigError::Help),
+        "version" => return Err(ConfigError::Version),
+        // Every flag in args::FLAGS is handled above.
+        _ => unreachable!("unhandled flag --{}", name),
+      }
+    }
+
+    // With -e or -f every positional argument is a path,
+    // and an empty pattern file matches nothing.
+    if !queries_given {
+      if positional.is_empty() {
+        return Err(ConfigError::NotEnoughArguments {
+          expected: 1,
+          got: 0,
+        });
+      }
+
+      queries.push(positional.remove(0));
+    }
+
+    // Without paths, like system grep, we read from stdin.
+    let paths = if positional.is_empty() {
+      vec![STDIN.to_string()]
+    } else {
+      positional
+    };
+
+    let case_insensitive = match case {
+      Case::Sensitive => false,
+      Case::Insensitive => true,
+      Case::Smart => !queries.iter().any(|query| has_uppercase(query)),
+    };
+
+    let pattern = Pattern::new(queries.clone(), fixed_strings, case_insensitive, bounds)
+      .map_err(ConfigError::InvalidPattern)?;
+
+    // JSON records are never colored.
+    if color.enabled() && !output.json {
+      let spec = env::var(color::COLORS_VAR).unwrap_or_default();
+      output.colors = Some(Colors::parse(&spec));
     }
 
-    let query = args[1].clone();
-    let filename = args[2].clone();
-    let case_insensitive = env::var("CASE_INSENSITIVE").is_err();
+    // --in-place and --backup mean nothing without --replace.
+    let replace = template.map(|template| Replace {
+      template,
+      in_place,
+      backup,
+    });
 
     Ok(Self {
-      query,
-      filename,
-      case_insensitive,
+      pattern,
+      queries,
+      paths,
+      filter,
+      search,
+      output,
+      threads,
+      sort,
+      replace,
+      decompress,
     })
   }
 }
 
+fn parse_glob(glob: &str) -> Result<Glob, ConfigError> {
+  Glob::new(glob).map_err(|error| ConfigError::InvalidGlob {
+    glob: glob.to_string(),
+    error,
+  })
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -93,6 +1142,19 @@ Duct tape.";
     assert_eq!(vec!["safe, fast, productive."], search(query, contents));
   }
 
+  #[test]
+  fn regex() {
+    let regex = Regex::new("^(safe|Pick) ", false).unwrap();
+
+    let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+
+    assert_eq!(vec!["Pick three."], search_regex(&regex, contents));
+  }
+
   #[test]
   fn case_insensitive() {
     let query = "RuSt";
@@ -108,4 +1170,184 @@ Trust me.";
       search_case_insensitive(query, contents)
     );
   }
+
+  const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!";
+
+  fn numbers(lines: &[Line]) -> Vec<(usize, LineKind)> {
+    lines.iter().map(|line| (line.number, line.kind)).collect()
+  }
+
+  #[test]
+  fn context_lines() {
+    let pattern = Regex::new("^How", false).unwrap();
+    let pattern = Pattern::Regex(pattern);
+
+    let options = SearchOptions {
+      before: 1,
+      after: 1,
+      ..SearchOptions::default()
+    };
+
+    assert_eq!(
+      vec![
+        (5, LineKind::Context),
+        (6, LineKind::Match),
+        (7, LineKind::Match),
+        (8, LineKind::Context),
+      ],
+      numbers(&search_lines(&pattern, POEM, &options))
+    );
+  }
+
+  #[test]
+  fn invert_match() {
+    let pattern = Pattern::Fixed {
+      query: "nobody".to_string(),
+      case_insensitive: false,
+    };
+
+    let options = SearchOptions {
+      invert: true,
+      ..SearchOptions::default()
+    };
+
+    let lines = search_lines(&pattern, POEM, &options);
+
+    assert_eq!(7, lines.len());
+    assert!(lines.iter().all(|line| !line.text.contains("nobody")));
+  }
+
+  #[test]
+  fn max_count_keeps_trailing_context() {
+    let pattern = Pattern::Fixed {
+      query: "you".to_string(),
+      case_insensitive: true,
+    };
+
+    let options = SearchOptions {
+      after: 1,
+      max_count: Some(2),
+      ..SearchOptions::default()
+    };
+
+    assert_eq!(
+      vec![
+        (1, LineKind::Match),
+        (2, LineKind::Match),
+        (3, LineKind::Context),
+      ],
+      numbers(&search_lines(&pattern, POEM, &options))
+    );
+  }
+
+  fn config(args: &[&str]) -> Config {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Config::new(&args).unwrap()
+  }
+
+  #[test]
+  fn replace_templates_cant_add_lines() {
+    let args: Vec<String> = ["grep", "--replace", "a\nb", "x", "-"]
+      .iter()
+      .map(|arg| arg.to_string())
+      .collect();
+
+    assert!(matches!(
+      Config::new(&args),
+      Err(ConfigError::TemplateNewline { template }) if template == "a\nb"
+    ));
+
+    // A backslash followed by n is only that.
+    assert!(config(&["grep", "--replace", r"a\nb", "x", "-"]).replace.is_some());
+  }
+
+  #[test]
+  fn case_options() {
+    // The last of -i, -s and -S wins.
+    assert!(config(&["grep", "-s", "-i", "rust", "-"])
+      .pattern
+      .is_match("RUST"));
+    assert!(!config(&["grep", "-i", "-s", "rust", "-"])
+      .pattern
+      .is_match("RUST"));
+
+    // Smart case looks past escapes like \S.
+    assert!(config(&["grep", "-S", r"ru\St", "-"])
+      .pattern
+      .is_match("RUST"));
+    assert!(!config(&["grep", "-S", "Rust", "-"])
+      .pattern
+      .is_match("RUST"));
+  }
+
+  #[test]
+  fn whole_words_and_lines() {
+    let words = config(&["grep", "-w", "-F", "-e", "a.", "-e", "ne", "-"]).pattern;
+
+    assert!(words.is_match("x a. y"));
+    assert!(!words.is_match("ba. y"));
+    assert!(words.is_match("(ne)"));
+    // A combining accent after the e makes it part of the word.
+    assert!(!words.is_match("ne\u{301}"));
+
+    let lines = config(&["grep", "-x", "-i", "straße|rust", "-"]).pattern;
+
+    assert!(lines.is_match("STRAẞE"));
+    assert!(!lines.is_match("rusty"));
+  }
+
+  #[test]
+  fn empty_pattern_file() {
+    let path = std::env::temp_dir().join(format!("grep-empty-{}.pats", std::process::id()));
+    fs::write(&path, "").unwrap();
+
+    let config = config(&["grep", "-f", path.to_str().unwrap(), "a.txt", "b.txt"]);
+    fs::remove_file(&path).unwrap();
+
+    // Every positional argument is a path, none of them the pattern.
+    assert_eq!(vec!["a.txt", "b.txt"], config.paths);
+    assert!(config.queries.is_empty());
+    assert!(!config.pattern.is_match("a.txt"));
+    assert!(!config.pattern.is_match(""));
+  }
+
+  #[test]
+  fn many_patterns() {
+    let queries = vec!["frog".to_string(), "bog".to_string(), "nobody".to_string()];
+    let pattern = Pattern::new(queries, false, false, Bounds::Anywhere).unwrap();
+
+    assert!(matches!(pattern, Pattern::Literals(_)));
+    assert_eq!(Some(1), pattern.find("To an admiring bog!"));
+    assert_eq!(Some(2), pattern.find("I'm nobody! Who are you?"));
+    assert_eq!(None, pattern.find("How dreary to be somebody!"));
+
+    let queries = vec!["^How d".to_string(), "frog$".to_string()];
+    let pattern = Pattern::new(queries, false, false, Bounds::Anywhere).unwrap();
+
+    assert_eq!(Some(1), pattern.find("How public, like a frog"));
+  }
+
+  #[test]
+  fn longest_of_the_leftmost_matches() {
+    let literals = config(&["grep", "-e", "abcd", "-e", "bc"]).pattern;
+    let matches: Vec<_> = literals.matches("abcd bc").collect();
+
+    assert!(matches!(literals, Pattern::Literals(_)));
+    assert_eq!(vec![(0, 4), (5, 7)], matches);
+
+    // The same with regular expressions.
+    let regexes = config(&["grep", "-e", "ab", "-e", "a.c."]).pattern;
+    let matches: Vec<_> = regexes.matches("abcd").collect();
+
+    assert_eq!(vec![(0, 4)], matches);
+  }
 }
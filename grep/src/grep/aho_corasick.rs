@@ -0,0 +1,256 @@
+// Aho-Corasick: searches for many literal patterns in one pass.
+//
+// The patterns are inserted into a trie. Every node also gets a failure
+// link, pointing to the node for the longest proper suffix of its path
+// that is also in the trie. While scanning, when the next character
+// has no edge out of the current node we follow failure links instead
+// of starting over, so every character of the text is looked at once
+// no matter how many patterns there are.
+//
+// Like system grep, the match returned is the leftmost one, and the
+// longest of those starting there: with abcd and bc, abcd matches
+// whole even though bc ends first.
+use std::collections::{HashMap, VecDeque};
+
+use super::case::fold;
+
+#[derive(Debug, Default)]
+struct Node {
+  next: HashMap<char, usize>,
+  fail: usize,
+  // Length of the path from the root, in characters.
+  depth: usize,
+  // Patterns that end at this node, including the ones
+  // reachable through failure links, longest first.
+  outputs: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+  /// Index of the pattern that matched.
+  pub pattern: usize,
+  pub start: usize,
+  pub end: usize,
+}
+
+#[derive(Debug)]
+pub struct AhoCorasick {
+  nodes: Vec<Node>,
+  // Length of each pattern, in characters.
+  lengths: Vec<usize>,
+  case_insensitive: bool,
+}
+
+const ROOT: usize = 0;
+
+impl AhoCorasick {
+  pub fn new(patterns: &[String], case_insensitive: bool) -> Self {
+    let mut nodes = vec![Node::default()];
+    let mut lengths = Vec::with_capacity(patterns.len());
+
+    for (index, pattern) in patterns.iter().enumerate() {
+      let mut node = ROOT;
+
+      for c in pattern.chars() {
+        let c = if case_insensitive { fold(c) } else { c };
+
+        node = match nodes[node].next.get(&c) {
+          Some(&next) => next,
+          None => {
+            nodes.push(Node {
+              depth: nodes[node].depth + 1,
+              ..Node::default()
+            });
+            let next = nodes.len() - 1;
+            nodes[node].next.insert(c, next);
+            next
+          }
+        };
+      }
+
+      nodes[node].outputs.push(index);
+      lengths.push(pattern.chars().count());
+    }
+
+    // Failure links are computed breadth first, so the link of a
+    // node's parent is always ready by the time the node is reached.
+    let mut queue: VecDeque<usize> = nodes[ROOT].next.values().copied().collect();
+
+    while let Some(node) = queue.pop_front() {
+      let edges: Vec<(char, usize)> = nodes[node].next.iter().map(|(&c, &n)| (c, n)).collect();
+
+      for (c, child) in edges {
+        let mut fail = nodes[node].fail;
+
+        while fail != ROOT && !nodes[fail].next.contains_key(&c) {
+          fail = nodes[fail].fail;
+        }
+
+        let fail = match nodes[fail].next.get(&c) {
+          Some(&next) if next != child => next,
+          _ => ROOT,
+        };
+
+        nodes[child].fail = fail;
+
+        let inherited = nodes[fail].outputs.clone();
+        nodes[child].outputs.extend(inherited);
+
+        queue.push_back(child);
+      }
+    }
+
+    Self {
+      nodes,
+      lengths,
+      case_insensitive,
+    }
+  }
+
+  pub fn is_match(&self, text: &str) -> bool {
+    self.find_at(text, 0).is_some()
+  }
+
+  /// Returns the leftmost-longest match at or after `start`.
+  pub fn find_at(&self, text: &str, start: usize) -> Option<Match> {
+    // Byte offset of every character seen so far, and of the end,
+    // to turn lengths in characters back into byte offsets.
+    let mut offsets = vec![start];
+    // The best match so far, with its start counted in characters.
+    let mut best: Option<(usize, Match)> = None;
+
+    // An empty pattern matches right away, a longer one may still win.
+    if let Some(&pattern) = self.nodes[ROOT].outputs.first() {
+      best = Some((
+        0,
+        Match {
+          pattern,
+          start,
+          end: start,
+        },
+      ));
+    }
+
+    let mut node = ROOT;
+
+    for (i, c) in text[start..].char_indices() {
+      let c = if self.case_insensitive { fold(c) } else { c };
+
+      while node != ROOT && !self.nodes[node].next.contains_key(&c) {
+        node = self.nodes[node].fail;
+      }
+
+      node = self.nodes[node].next.get(&c).copied().unwrap_or(ROOT);
+      offsets.push(start + i + c.len_utf8());
+
+      let seen = offsets.len() - 1;
+
+      // Every match from here on is a suffix of the current path
+      // followed by more text, so once the path starts after the
+      // best match, nothing can start as far left.
+      if let Some((best_start, _)) = best {
+        if seen - self.nodes[node].depth > best_start {
+          break;
+        }
+      }
+
+      // Outputs are longest first, so the first one starts leftmost.
+      if let Some(&pattern) = self.nodes[node].outputs.first() {
+        let begin = seen - self.lengths[pattern];
+
+        // A match ending later with the same start is longer.
+        if best.is_none_or(|(best_start, _)| begin <= best_start) {
+          best = Some((
+            begin,
+            Match {
+              pattern,
+              start: offsets[begin],
+              end: offsets[seen],
+            },
+          ));
+        }
+      }
+    }
+
+    best.map(|(_, m)| m)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn patterns(patterns: &[&str]) -> Vec<String> {
+    patterns.iter().map(|pattern| pattern.to_string()).collect()
+  }
+
+  #[test]
+  fn finds_every_pattern() {
+    let automaton = AhoCorasick::new(&patterns(&["he", "she", "his", "hers"]), false);
+
+    assert_eq!(
+      Some(Match {
+        pattern: 1,
+        start: 1,
+        end: 4
+      }),
+      automaton.find_at("ushers", 0)
+    );
+    assert_eq!(Some(2), automaton.find_at("this", 0).map(|m| m.pattern));
+    assert_eq!(None, automaton.find_at("hi", 0));
+  }
+
+  #[test]
+  fn follows_failure_links() {
+    let automaton = AhoCorasick::new(&patterns(&["abcd", "bce"]), false);
+
+    assert_eq!(
+      Some(Match {
+        pattern: 1,
+        start: 1,
+        end: 4
+      }),
+      automaton.find_at("abce", 0)
+    );
+  }
+
+  #[test]
+  fn leftmost_longest() {
+    let automaton = AhoCorasick::new(&patterns(&["abcd", "bc"]), false);
+
+    assert_eq!(
+      Some(Match {
+        pattern: 0,
+        start: 0,
+        end: 4
+      }),
+      automaton.find_at("abcd", 0)
+    );
+    // bc ends first and starts further left than anything after it.
+    assert_eq!(
+      Some(Match {
+        pattern: 1,
+        start: 1,
+        end: 3
+      }),
+      automaton.find_at("abcx bcd", 0)
+    );
+
+    let automaton = AhoCorasick::new(&patterns(&["", "ab", "a"]), false);
+    assert_eq!(
+      Some((0, 2)),
+      automaton.find_at("ab", 0).map(|m| (m.start, m.end))
+    );
+    assert_eq!(
+      Some((0, 0)),
+      automaton.find_at("xab", 0).map(|m| (m.start, m.end))
+    );
+  }
+
+  #[test]
+  fn case_insensitive() {
+    let automaton = AhoCorasick::new(&patterns(&["RUST"]), true);
+
+    assert!(automaton.is_match("Trust me."));
+  }
+}
@@ -0,0 +1,358 @@
+// Splits the command line into flags and positional arguments.
+//
+// Every flag has a long name and may also have a short one:
+//
+//   --count  -c          flags without a value
+//   -nc                  short flags can be grouped
+//   --context 2  --context=2  -C 2  -C2
+//                        flags that take a value
+//   --                   everything after it is positional,
+//                        so patterns can start with -
+//
+// The table below is also where --help gets its text from.
+use super::ConfigError;
+
+pub struct Flag {
+  pub short: Option<char>,
+  pub long: &'static str,
+  /// Name of the value the flag takes, None if it takes none.
+  pub value: Option<&'static str>,
+  pub help: &'static str,
+}
+
+const fn flag(short: Option<char>, long: &'static str, help: &'static str) -> Flag {
+  Flag {
+    short,
+    long,
+    value: None,
+    help,
+  }
+}
+
+const fn with_value(
+  short: Option<char>,
+  long: &'static str,
+  value: &'static str,
+  help: &'static str,
+) -> Flag {
+  Flag {
+    short,
+    long,
+    value: Some(value),
+    help,
+  }
+}
+
+pub const FLAGS: &[Flag] = &[
+  with_value(
+    Some('e'),
+    "regexp",
+    "PATTERN",
+    "search for PATTERN, may be repeated",
+  ),
+  with_value(
+    Some('f'),
+    "file",
+    "FILE",
+    "search for every pattern in FILE, one per line",
+  ),
+  flag(
+    Some('F'),
+    "fixed-strings",
+    "treat patterns as literal strings",
+  ),
+  flag(Some('i'), "ignore-case", "match case insensitively"),
+  flag(
+    Some('s'),
+    "case-sensitive",
+    "match case sensitively (default)",
+  ),
+  flag(
+    Some('S'),
+    "smart-case",
+    "ignore case unless a pattern has an uppercase letter",
+  ),
+  flag(Some('w'), "word-regexp", "only match whole words"),
+  flag(Some('x'), "line-regexp", "only match whole lines"),
+  flag(
+    Some('v'),
+    "invert-match",
+    "select the lines that don't match",
+  ),
+  flag(Some('n'), "line-number", "print line numbers"),
+  flag(
+    Some('c'),
+    "count",
+    "print the number of selected lines per file",
+  ),
+  flag(
+    Some('l'),
+    "files-with-matches",
+    "print only the names of files with matches",
+  ),
+  flag(
+    Some('L'),
+    "files-without-match",
+    "print only the names of files without matches",
+  ),
+  with_value(
+    Some('A'),
+    "after-context",
+    "NUM",
+    "print NUM lines after each match",
+  ),
+  with_value(
+    Some('B'),
+    "before-context",
+    "NUM",
+    "print NUM lines before each match",
+  ),
+  with_value(
+    Some('C'),
+    "context",
+    "NUM",
+    "print NUM lines around each match",
+  ),
+  with_value(
+    Some('m'),
+    "max-count",
+    "NUM",
+    "stop after NUM selected lines",
+  ),
+  with_value(None, "include", "GLOB", "only search files matching GLOB"),
+  with_value(None, "exclude", "GLOB", "skip files matching GLOB"),
+  flag(
+    Some('R'),
+    "dereference-recursive",
+    "follow symbolic links to directories",
+  ),
+  with_value(Some('j'), "threads", "NUM", "search NUM files at once"),
+  flag(
+    Some('z'),
+    "search-zip",
+    "search in gzip, bzip2 and xz compressed files",
+  ),
+  flag(None, "sort", "print files in path order"),
+  flag(
+    None,
+    "show-pattern",
+    "prefix lines with the pattern that matched",
+  ),
+  with_value(None, "color", "WHEN", "color output: auto, always or never"),
+  flag(None, "json", "print results as JSON Lines"),
+  with_value(
+    None,
+    "replace",
+    "TEMPLATE",
+    "show matches replaced by TEMPLATE as a diff",
+  ),
+  flag(
+    None,
+    "in-place",
+    "with --replace, write the changes to the files",
+  ),
+  with_value(
+    None,
+    "backup",
+    "SUFFIX",
+    "with --in-place, keep originals with SUFFIX",
+  ),
+  flag(Some('h'), "help", "print this help and exit"),
+  flag(Some('V'), "version", "print the version and exit"),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Arg {
+  /// A flag without a value, by its long name.
+  Flag(&'static str),
+  /// A flag and its value.
+  Value(&'static str, String),
+  Positional(String),
+}
+
+fn find_long(name: &str) -> Option<&'static Flag> {
+  FLAGS.iter().find(|flag| flag.long == name)
+}
+
+fn find_short(c: char) -> Option<&'static Flag> {
+  FLAGS.iter().find(|flag| flag.short == Some(c))
+}
+
+/// Parses `args`, which don't include the program name.
+pub fn parse(args: &[String]) -> Result<Vec<Arg>, ConfigError> {
+  let mut parsed = Vec::new();
+  let mut args = args.iter();
+
+  while let Some(arg) = args.next() {
+    if arg == "--" {
+      parsed.extend(args.by_ref().cloned().map(Arg::Positional));
+      break;
+    }
+
+    if let Some(long) = arg.strip_prefix("--") {
+      let (name, value) = match long.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (long, None),
+      };
+
+      let flag = find_long(name).ok_or_else(|| ConfigError::UnknownFlag {
+        flag: format!("--{}", name),
+      })?;
+
+      match (flag.value, value) {
+        (None, None) => parsed.push(Arg::Flag(flag.long)),
+        (None, Some(_)) => {
+          return Err(ConfigError::UnexpectedValue {
+            flag: format!("--{}", name),
+          })
+        }
+        (Some(_), Some(value)) => parsed.push(Arg::Value(flag.long, value)),
+        (Some(_), None) => {
+          let value = args.next().ok_or_else(|| ConfigError::MissingValue {
+            flag: format!("--{}", name),
+          })?;
+          parsed.push(Arg::Value(flag.long, value.clone()));
+        }
+      }
+
+      continue;
+    }
+
+    // A lone - stands for stdin.
+    let shorts = match arg.strip_prefix('-') {
+      Some(shorts) if !shorts.is_empty() => shorts,
+      _ => {
+        parsed.push(Arg::Positional(arg.clone()));
+        continue;
+      }
+    };
+
+    for (i, c) in shorts.char_indices() {
+      let flag = find_short(c).ok_or_else(|| ConfigError::UnknownFlag {
+        flag: format!("-{}", c),
+      })?;
+
+      if flag.value.is_none() {
+        parsed.push(Arg::Flag(flag.long));
+        continue;
+      }
+
+      // The rest of the group is the value, as in -C2.
+      let rest = &shorts[i + c.len_utf8()..];
+
+      let value = if rest.is_empty() {
+        args
+          .next()
+          .ok_or_else(|| ConfigError::MissingValue {
+            flag: format!("-{}", c),
+          })?
+          .clone()
+      } else {
+        rest.to_string()
+      };
+
+      parsed.push(Arg::Value(flag.long, value));
+      break;
+    }
+  }
+
+  Ok(parsed)
+}
+
+/// The text printed by --help.
+pub fn help() -> String {
+  let names: Vec<String> = FLAGS
+    .iter()
+    .map(|flag| {
+      let short = match flag.short {
+        Some(c) => format!("-{}, ", c),
+        None => "    ".to_string(),
+      };
+
+      match flag.value {
+        Some(value) => format!("{}--{} {}", short, flag.long, value),
+        None => format!("{}--{}", short, flag.long),
+      }
+    })
+    .collect();
+
+  let width = names.iter().map(String::len).max().unwrap_or(0);
+
+  let mut help = String::from(
+    "\
+Usage: grep [OPTIONS] PATTERN [PATH...]
+       grep [OPTIONS] -e PATTERN... [PATH...]
+
+Searches every PATH, or stdin when there is none, for lines matching PATTERN.
+Directories are searched recursively, without following symbolic links
+to directories unless -R is given.
+
+Options:
+",
+  );
+
+  for (name, flag) in names.iter().zip(FLAGS) {
+    help.push_str(&format!(
+      "  {:width$}  {}\n",
+      name,
+      flag.help,
+      width = width
+    ));
+  }
+
+  help
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+  }
+
+  #[test]
+  fn parses_flags_values_and_positionals() {
+    let parsed = parse(&args(&[
+      "-nC2",
+      "--max-count",
+      "3",
+      "--color=never",
+      "-",
+      "-e",
+      "x",
+      "--",
+      "-v",
+    ]))
+    .unwrap();
+
+    assert_eq!(
+      vec![
+        Arg::Flag("line-number"),
+        Arg::Value("context", "2".to_string()),
+        Arg::Value("max-count", "3".to_string()),
+        Arg::Value("color", "never".to_string()),
+        Arg::Positional("-".to_string()),
+        Arg::Value("regexp", "x".to_string()),
+        Arg::Positional("-v".to_string()),
+      ],
+      parsed
+    );
+  }
+
+  #[test]
+  fn reports_bad_flags() {
+    assert!(matches!(
+      parse(&args(&["-q"])),
+      Err(ConfigError::UnknownFlag { flag }) if flag == "-q"
+    ));
+    assert!(matches!(
+      parse(&args(&["--count=2"])),
+      Err(ConfigError::UnexpectedValue { flag }) if flag == "--count"
+    ));
+    assert!(matches!(
+      parse(&args(&["x", "-A"])),
+      Err(ConfigError::MissingValue { flag }) if flag == "-A"
+    ));
+  }
+}
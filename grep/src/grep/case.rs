@@ -0,0 +1,104 @@
+// Unicode simple case folding.
+//
+// Folding maps every character to a representative of its case
+// class, so two strings are equal ignoring case when their folded
+// characters are. Simple folding always maps one character to one
+// character, ß stays ß instead of becoming ss like full folding
+// does, so strings can be compared character by character without
+// building folded copies of them.
+//
+// Lowercasing gets it right for almost every character, the ones
+// below fold to something else because they are already lowercase
+// variants of another letter.
+const SPECIAL: [(char, char); 22] = [
+  ('\u{00b5}', 'μ'), // µ micro sign
+  ('\u{017f}', 's'), // ſ long s
+  ('\u{0345}', 'ι'), // combining ypogegrammeni
+  ('ς', 'σ'),
+  ('ϐ', 'β'),
+  ('ϑ', 'θ'),
+  ('ϕ', 'φ'),
+  ('ϖ', 'π'),
+  ('ϰ', 'κ'),
+  ('ϱ', 'ρ'),
+  ('ϵ', 'ε'),
+  ('ẛ', 'ṡ'),
+  ('\u{1fbe}', 'ι'), // Greek prosgegrammeni
+  // Old Cyrillic letter forms.
+  ('ᲀ', 'в'),
+  ('ᲁ', 'д'),
+  ('ᲂ', 'о'),
+  ('ᲃ', 'с'),
+  ('ᲄ', 'т'),
+  ('ᲅ', 'т'),
+  ('ᲆ', 'ъ'),
+  ('ᲇ', 'ѣ'),
+  ('ᲈ', 'ꙋ'),
+];
+
+/// Folds `c` to the character that stands for its whole case class.
+pub fn fold(c: char) -> char {
+  if c.is_ascii() {
+    return c.to_ascii_lowercase();
+  }
+
+  if let Some(&(_, folded)) = SPECIAL.iter().find(|(special, _)| *special == c) {
+    return folded;
+  }
+
+  // Characters that lowercase to more than one character,
+  // like İ, have no simple folding and stay as they are.
+  let mut lower = c.to_lowercase();
+
+  match (lower.next(), lower.next()) {
+    (Some(l), None) => l,
+    _ => c,
+  }
+}
+
+/// Returns the byte range of the first occurrence of `needle` in
+/// `haystack` at or after `start`, ignoring case.
+///
+/// The range may not be as long as `needle`, folding can change
+/// how many bytes a character takes, like K (the Kelvin sign) and k.
+pub fn find(haystack: &str, needle: &str, start: usize) -> Option<(usize, usize)> {
+  if needle.is_empty() {
+    return Some((start, start));
+  }
+
+  haystack[start..].char_indices().find_map(|(i, _)| {
+    let begin = start + i;
+    let mut end = begin;
+    let mut text = haystack[begin..].chars();
+
+    for expected in needle.chars() {
+      match text.next() {
+        Some(c) if fold(c) == fold(expected) => end += c.len_utf8(),
+        _ => return None,
+      }
+    }
+
+    Some((begin, end))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn folds_special_characters() {
+    assert_eq!(fold('Σ'), fold('ς'));
+    assert_eq!(fold('S'), fold('ſ'));
+    assert_eq!('İ', fold('İ'));
+    assert_eq!('ß', fold('ẞ'));
+  }
+
+  #[test]
+  fn finds_ignoring_case() {
+    assert_eq!(Some((4, 12)), find("the ΟΔΥΣ end", "οδυς", 0));
+    // The Kelvin sign takes three bytes, k takes one.
+    assert_eq!(Some((2, 5)), find("5 \u{212a}elvin", "k", 0));
+    assert_eq!(None, find("Rust", "rust", 1));
+  }
+}
@@ -0,0 +1,137 @@
+// --color: highlights matches, file names and line numbers with
+// ANSI escape sequences.
+//
+// A colored piece of text starts with ESC[<params>m, where params are
+// SGR codes separated by ; like 01;31 for bold red, and ends with
+// ESC[m which resets everything back to normal.
+//
+// The colors can be changed with the GREP_COLORS environment variable,
+// which uses the same format as system grep, for example
+// GREP_COLORS='mt=01;32:fn=34:ln=33:se=36'.
+use std::io::{self, IsTerminal, Write};
+
+/// The environment variable the colors are read from.
+pub const COLORS_VAR: &str = "GREP_COLORS";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+  /// Colors only when stdout is a terminal.
+  Auto,
+  Always,
+  Never,
+}
+
+impl ColorChoice {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "auto" => Some(ColorChoice::Auto),
+      "always" => Some(ColorChoice::Always),
+      "never" => Some(ColorChoice::Never),
+      _ => None,
+    }
+  }
+
+  /// Escape sequences written to a pipe or a file would end
+  /// up as garbage in whatever reads them, so auto only
+  /// colors what goes straight to a terminal.
+  pub fn enabled(self) -> bool {
+    match self {
+      ColorChoice::Auto => io::stdout().is_terminal(),
+      ColorChoice::Always => true,
+      ColorChoice::Never => false,
+    }
+  }
+}
+
+/// SGR parameters for each part of the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+  /// mt: matched text.
+  pub matched: String,
+  /// fn: file names.
+  pub path: String,
+  /// ln: line numbers.
+  pub line_number: String,
+  /// se: separators, like : and --.
+  pub separator: String,
+}
+
+impl Default for Colors {
+  // The same defaults as system grep.
+  fn default() -> Self {
+    Self {
+      matched: "01;31".to_string(),
+      path: "35".to_string(),
+      line_number: "32".to_string(),
+      separator: "36".to_string(),
+    }
+  }
+}
+
+impl Colors {
+  /// Reads colors in the GREP_COLORS format, keeping the
+  /// default for everything `spec` doesn't mention.
+  ///
+  /// Unknown keys and invalid values are ignored like system grep does.
+  pub fn parse(spec: &str) -> Self {
+    let mut colors = Self::default();
+
+    for entry in spec.split(':') {
+      let (key, value) = match entry.split_once('=') {
+        Some(entry) => entry,
+        None => continue,
+      };
+
+      if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+        continue;
+      }
+
+      let color = match key {
+        // ms and mc color matches in selected and context lines,
+        // which are the same for us since context lines don't
+        // get their matches highlighted.
+        "mt" | "ms" => &mut colors.matched,
+        "fn" => &mut colors.path,
+        "ln" => &mut colors.line_number,
+        "se" => &mut colors.separator,
+        _ => continue,
+      };
+
+      *color = value.to_string();
+    }
+
+    colors
+  }
+}
+
+/// Writes `text` in the color `sgr`.
+pub fn paint(out: &mut impl Write, sgr: &str, text: &str) -> io::Result<()> {
+  write!(out, "\x1b[{}m\x1b[K{}\x1b[m\x1b[K", sgr, text)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_grep_colors() {
+    let colors = Colors::parse("mt=01;32:fn=34:ln=:se=bad:xx=1");
+
+    assert_eq!(
+      Colors {
+        matched: "01;32".to_string(),
+        path: "34".to_string(),
+        ..Colors::default()
+      },
+      colors
+    );
+  }
+
+  #[test]
+  fn paints() {
+    let mut out = Vec::new();
+    paint(&mut out, "01;31", "frog").unwrap();
+
+    assert_eq!(b"\x1b[01;31m\x1b[Kfrog\x1b[m\x1b[K", &out[..]);
+  }
+}
@@ -0,0 +1,89 @@
+// -z: searches the contents of compressed files.
+//
+// The format is recognised by the magic bytes every compressed
+// file starts with rather than by its extension, so renamed
+// files still work and plain files are searched as usual.
+use std::io::{self, BufRead, BufReader};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+  Gzip,
+  Bzip2,
+  Xz,
+}
+
+impl Format {
+  pub fn detect(header: &[u8]) -> Option<Self> {
+    if header.starts_with(&[0x1f, 0x8b]) {
+      Some(Format::Gzip)
+    } else if header.starts_with(b"BZh") {
+      Some(Format::Bzip2)
+    } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+      Some(Format::Xz)
+    } else {
+      None
+    }
+  }
+}
+
+/// Returns a reader for the decompressed contents of `reader`,
+/// or `reader` itself when it isn't compressed.
+pub fn reader<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+  // Files made by concatenating compressed files are valid too,
+  // so the multi stream decoders are used.
+  let reader: Box<dyn BufRead> = match Format::detect(reader.fill_buf()?) {
+    Some(Format::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+    Some(Format::Bzip2) => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+    Some(Format::Xz) => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+    None => Box::new(reader),
+  };
+
+  Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{Read, Write};
+
+  fn decompressed(compressed: &[u8]) -> io::Result<String> {
+    let mut contents = String::new();
+    reader(compressed)?.read_to_string(&mut contents)?;
+    Ok(contents)
+  }
+
+  #[test]
+  fn decompresses_every_format() {
+    let text = "How dreary to be somebody!\n";
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(text.as_bytes()).unwrap();
+
+    let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    bzip2.write_all(text.as_bytes()).unwrap();
+
+    let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+    xz.write_all(text.as_bytes()).unwrap();
+
+    for compressed in [
+      gzip.finish().unwrap(),
+      bzip2.finish().unwrap(),
+      xz.finish().unwrap(),
+    ]
+    .iter()
+    {
+      assert_eq!(text, decompressed(compressed).unwrap());
+    }
+
+    assert_eq!(text, decompressed(text.as_bytes()).unwrap());
+  }
+
+  #[test]
+  fn corrupt_archives_are_errors() {
+    assert!(decompressed(&[0x1f, 0x8b, 0x08, 0x00, 0xff, 0xff]).is_err());
+  }
+}
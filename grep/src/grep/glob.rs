@@ -0,0 +1,130 @@
+// Shell style globs, as used by --include, --exclude and ignore files.
+//
+// A glob is translated into a regular expression and compiled with
+// the same engine used to search, so there is just one matcher to maintain.
+//
+//   *     anything but a /
+//   ?     a single character but a /
+//   **    anything, including /
+//   [ab]  a character class, [!ab] negates it
+use super::regex::{Regex, RegexError};
+
+#[derive(Debug)]
+pub struct Glob {
+  regex: Regex,
+  // Globs with a / in them match the whole relative path,
+  // the others only match the file name.
+  anchored: bool,
+}
+
+impl Glob {
+  pub fn new(glob: &str) -> Result<Self, RegexError> {
+    let anchored = glob.contains('/');
+    let glob = glob.strip_prefix('/').unwrap_or(glob);
+
+    let chars: Vec<char> = glob.chars().collect();
+    let mut pattern = String::from("^");
+    let mut i = 0;
+
+    while i < chars.len() {
+      match chars[i] {
+        '*' if chars.get(i + 1) == Some(&'*') => {
+          i += 1;
+
+          if chars.get(i + 1) == Some(&'/') {
+            i += 1;
+            pattern.push_str("(?:.*/)?");
+          } else {
+            pattern.push_str(".*");
+          }
+        }
+        '*' => pattern.push_str("[^/]*"),
+        '?' => pattern.push_str("[^/]"),
+        '[' if chars[i + 1..].contains(&']') => {
+          pattern.push('[');
+          i += 1;
+
+          if chars[i] == '!' && i + 1 < chars.len() {
+            pattern.push('^');
+            i += 1;
+          }
+
+          // A ] right after the opening bracket is a literal.
+          if chars[i] == ']' {
+            pattern.push_str("\\]");
+            i += 1;
+          }
+
+          while i < chars.len() && chars[i] != ']' {
+            pattern.push(chars[i]);
+            i += 1;
+          }
+
+          pattern.push(']');
+        }
+        '\\' if i + 1 < chars.len() => {
+          i += 1;
+          push_literal(&mut pattern, chars[i]);
+        }
+        c => push_literal(&mut pattern, c),
+      }
+
+      i += 1;
+    }
+
+    pattern.push('$');
+
+    Ok(Self {
+      regex: Regex::new(&pattern, false)?,
+      anchored,
+    })
+  }
+
+  /// `path` is relative to the directory the glob applies to,
+  /// with / as separator.
+  pub fn is_match(&self, path: &str) -> bool {
+    if self.anchored {
+      self.regex.is_match(path)
+    } else {
+      let name = path.rsplit('/').next().unwrap_or(path);
+      self.regex.is_match(name)
+    }
+  }
+}
+
+fn push_literal(pattern: &mut String, c: char) {
+  if "\\.+()|^${}[]*?".contains(c) {
+    pattern.push('\\');
+  }
+
+  pattern.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn wildcards() {
+    let glob = Glob::new("*.rs").unwrap();
+    assert!(glob.is_match("main.rs"));
+    assert!(glob.is_match("src/grep/glob.rs"));
+    assert!(!glob.is_match("main.rs.bak"));
+
+    let glob = Glob::new("file?.[!t]xt").unwrap();
+    assert!(glob.is_match("file1.sxt"));
+    assert!(!glob.is_match("file1.txt"));
+  }
+
+  #[test]
+  fn anchored_globs_match_the_whole_path() {
+    let glob = Glob::new("/target").unwrap();
+    assert!(glob.is_match("target"));
+    assert!(!glob.is_match("src/target"));
+
+    let glob = Glob::new("src/**/*.rs").unwrap();
+    assert!(glob.is_match("src/main.rs"));
+    assert!(glob.is_match("src/grep/glob.rs"));
+    assert!(!glob.is_match("tests/main.rs"));
+  }
+}
@@ -0,0 +1,161 @@
+// --json: prints what is found as JSON Lines, one object per line,
+// so editors and other tools don't have to parse grep's normal output.
+//
+// Every object has a "type" and the fields of that type:
+//
+//   begin    {"type":"begin","path":"src/main.rs"}
+//   match    {"type":"match","path":...,"line_number":3,"text":"...",
+//             "submatches":[{"match":"...","start":4,"end":7}]}
+//   context  same fields as match, with no submatches
+//   end      {"type":"end","path":...,"stats":{...}}
+//   summary  {"type":"summary","stats":{...}}
+//
+// begin and end surround the lines of every file with selected lines,
+// summary comes once after every file was searched. Offsets are in
+// bytes, counted from the start of the line.
+use std::{
+  fmt::Write as _,
+  io::{self, Write},
+};
+
+use super::{Line, LineKind, Pattern};
+
+/// Counts of what was found, for one file or for the whole search.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+  pub searches: usize,
+  pub searches_with_match: usize,
+  pub matched_lines: usize,
+}
+
+impl Stats {
+  pub fn add(&mut self, other: Stats) {
+    self.searches += other.searches;
+    self.searches_with_match += other.searches_with_match;
+    self.matched_lines += other.matched_lines;
+  }
+
+  fn to_json(self) -> String {
+    format!(
+      r#"{{"searches":{},"searches_with_match":{},"matched_lines":{}}}"#,
+      self.searches, self.searches_with_match, self.matched_lines
+    )
+  }
+}
+
+/// Quotes `s` as a JSON string.
+fn string(s: &str) -> String {
+  let mut quoted = String::with_capacity(s.len() + 2);
+  quoted.push('"');
+
+  for c in s.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      // Other control characters can only be written as escapes.
+      c if (c as u32) < 0x20 => {
+        let _ = write!(quoted, "\\u{:04x}", c as u32);
+      }
+      c => quoted.push(c),
+    }
+  }
+
+  quoted.push('"');
+  quoted
+}
+
+pub fn begin(out: &mut impl Write, path: &str) -> io::Result<()> {
+  writeln!(out, r#"{{"type":"begin","path":{}}}"#, string(path))
+}
+
+pub fn line(out: &mut impl Write, path: &str, line: &Line, pattern: &Pattern) -> io::Result<()> {
+  let kind = match line.kind {
+    LineKind::Match => "match",
+    LineKind::Context => "context",
+  };
+
+  // Context lines and the lines selected by -v don't match.
+  let submatches: Vec<String> = match line.kind {
+    LineKind::Match => pattern
+      .matches(&line.text)
+      .map(|(start, end)| {
+        format!(
+          r#"{{"match":{},"start":{},"end":{}}}"#,
+          string(&line.text[start..end]),
+          start,
+          end
+        )
+      })
+      .collect(),
+    LineKind::Context => Vec::new(),
+  };
+
+  writeln!(
+    out,
+    r#"{{"type":"{}","path":{},"line_number":{},"text":{},"submatches":[{}]}}"#,
+    kind,
+    string(path),
+    line.number,
+    string(&line.text),
+    submatches.join(",")
+  )
+}
+
+pub fn end(out: &mut impl Write, path: &str, stats: Stats) -> io::Result<()> {
+  writeln!(
+    out,
+    r#"{{"type":"end","path":{},"stats":{}}}"#,
+    string(path),
+    stats.to_json()
+  )
+}
+
+pub fn summary(out: &mut impl Write, stats: Stats) -> io::Result<()> {
+  writeln!(out, r#"{{"type":"summary","stats":{}}}"#, stats.to_json())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::grep::Bounds;
+  use std::borrow::Cow;
+
+  #[test]
+  fn escapes_strings() {
+    assert_eq!(r#""a\"b\\c\n\u0001é""#, string("a\"b\\c\n\u{1}é"));
+  }
+
+  #[test]
+  fn match_record() {
+    let pattern = Pattern::new(vec!["o".to_string()], false, false, Bounds::Anywhere).unwrap();
+    let mut out = Vec::new();
+
+    line(
+      &mut out,
+      "poem.txt",
+      &Line {
+        number: 2,
+        text: Cow::Borrowed("a \"frog\" or bog"),
+        kind: LineKind::Match,
+        pattern: Some(0),
+      },
+      &pattern,
+    )
+    .unwrap();
+
+    assert_eq!(
+      concat!(
+        r#"{"type":"match","path":"poem.txt","line_number":2,"#,
+        r#""text":"a \"frog\" or bog","submatches":["#,
+        r#"{"match":"o","start":5,"end":6},"#,
+        r#"{"match":"o","start":9,"end":10},"#,
+        r#"{"match":"o","start":13,"end":14}]}"#,
+        "\n"
+      ),
+      String::from_utf8(out).unwrap()
+    );
+  }
+}
@@ -0,0 +1,84 @@
+// The same kind of thread pool as the one in multithreaded_web_server,
+// used to search many files at once.
+use std::{
+  sync::{mpsc, Arc, Mutex},
+  thread,
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+enum Message {
+  /// Holds a job that the thread should run.
+  NewJob(Job),
+  /// Tells the thread to exit its loop and stop.
+  Terminate,
+}
+
+pub struct ThreadPool {
+  workers: Vec<Worker>,
+  sender: mpsc::Sender<Message>,
+}
+
+impl ThreadPool {
+  /// Create a new ThreadPool.
+  ///
+  /// `size` is the number of threads in the pool.
+  ///
+  /// # Panics
+  ///
+  /// The `new` function will panic if the size is 0.
+  pub fn new(size: usize) -> Self {
+    assert!(size > 0);
+
+    let (sender, receiver) = mpsc::channel();
+
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let workers = (0..size)
+      .map(|_| Worker::new(Arc::clone(&receiver)))
+      .collect();
+
+    Self { workers, sender }
+  }
+
+  pub fn execute(&self, f: impl FnOnce() + Send + 'static) {
+    self.sender.send(Message::NewJob(Box::new(f))).unwrap();
+  }
+}
+
+impl Drop for ThreadPool {
+  fn drop(&mut self) {
+    // Jobs that were already queued still run,
+    // the Terminate messages are queued after them.
+    for _ in &self.workers {
+      self.sender.send(Message::Terminate).unwrap();
+    }
+
+    for worker in &mut self.workers {
+      if let Some(thread) = worker.thread.take() {
+        thread.join().unwrap();
+      }
+    }
+  }
+}
+
+struct Worker {
+  thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+  fn new(receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Self {
+    let thread = thread::spawn(move || loop {
+      let message = receiver.lock().unwrap().recv().unwrap();
+
+      match message {
+        Message::NewJob(job) => job(),
+        Message::Terminate => break,
+      }
+    });
+
+    Self {
+      thread: Some(thread),
+    }
+  }
+}
@@ -0,0 +1,1078 @@
+// A small regular expression engine.
+//
+// The pattern is parsed into a syntax tree, the tree is compiled
+// into a program for a Thompson NFA, and the program is executed
+// with a Pike VM, which simulates every possible NFA state at once.
+// Unlike a backtracking engine, the time it takes is linear
+// in the size of the text, no matter how the pattern looks.
+//
+// Supported syntax:
+//
+//   .            any character
+//   [a-z] [^0-9] character classes, with [:alpha:] style classes inside
+//   \d \w \s     digits, word characters, whitespace (\D \W \S negate them)
+//   ^ $          start and end of the line
+//   a|b          alternation
+//   (a) (?:a)    capturing and non-capturing groups
+//   * + ? {n} {n,} {n,m}
+//                repetition, greedy unless followed by ?
+use std::{error::Error, fmt};
+
+use super::case::fold;
+
+/// How many times a bounded repetition may repeat,
+/// each repetition is compiled into a copy of its expression.
+const MAX_REPEAT: u32 = 1000;
+
+/// How many instructions a compiled pattern may have. Repetitions
+/// multiply, ((a{1000}){1000}){1000} would need a billion of them.
+const MAX_SIZE: usize = 100_000;
+
+/// How deeply groups and repetitions may nest. Parsing, compiling and
+/// dropping the syntax tree recurse once per level, too many levels
+/// would overflow the stack.
+const MAX_DEPTH: usize = 250;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+  message: String,
+  // Position in the pattern, counted in characters.
+  position: usize,
+}
+
+impl fmt::Display for RegexError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at position {}", self.message, self.position)
+  }
+}
+
+impl Error for RegexError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Named {
+  Digit,
+  Word,
+  Space,
+  Alpha,
+  Alnum,
+  Upper,
+  Lower,
+  Punct,
+}
+
+impl Named {
+  fn matches(self, c: char) -> bool {
+    match self {
+      Named::Digit => c.is_ascii_digit(),
+      Named::Word => is_word_char(c),
+      Named::Space => c.is_whitespace(),
+      Named::Alpha => c.is_alphabetic(),
+      Named::Alnum => c.is_alphanumeric(),
+      Named::Upper => c.is_uppercase(),
+      Named::Lower => c.is_lowercase(),
+      Named::Punct => c.is_ascii_punctuation(),
+    }
+  }
+
+  fn from_posix(name: &str) -> Option<Self> {
+    match name {
+      "digit" => Some(Named::Digit),
+      "space" => Some(Named::Space),
+      "alpha" => Some(Named::Alpha),
+      "alnum" => Some(Named::Alnum),
+      "upper" => Some(Named::Upper),
+      "lower" => Some(Named::Lower),
+      "punct" => Some(Named::Punct),
+      _ => None,
+    }
+  }
+}
+
+/// Word characters as Unicode defines them for \w, close enough:
+/// letters and digits of any script, connector punctuation like _,
+/// the combining marks that follow a letter in decomposed text
+/// and the joiners used inside words in some scripts.
+pub(super) fn is_word_char(c: char) -> bool {
+  c.is_alphanumeric()
+    || matches!(
+      c,
+      '_' | '\u{203f}' | '\u{2040}' | '\u{0300}'..='\u{036f}' | '\u{200c}' | '\u{200d}'
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+  Range(char, char),
+  Named { class: Named, negated: bool },
+}
+
+impl ClassItem {
+  fn matches(&self, c: char) -> bool {
+    match *self {
+      ClassItem::Range(start, end) => start <= c && c <= end,
+      ClassItem::Named { class, negated } => class.matches(c) != negated,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+  items: Vec<ClassItem>,
+  negated: bool,
+}
+
+impl Class {
+  fn matches(&self, c: char, case_insensitive: bool) -> bool {
+    let hit = |c: char| self.items.iter().any(|item| item.matches(c));
+
+    let found = hit(c) || (case_insensitive && (hit(fold(c)) || c.to_uppercase().any(hit)));
+
+    found != self.negated
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+  Start,
+  End,
+  WordBoundary,
+  NotWordBoundary,
+  // No syntax, only used by -w: the character before or
+  // after the position is not a word character.
+  NotAfterWord,
+  NotBeforeWord,
+}
+
+impl Assertion {
+  fn holds(self, text: &str, pos: usize) -> bool {
+    match self {
+      Assertion::Start => pos == 0,
+      Assertion::End => pos == text.len(),
+      Assertion::WordBoundary | Assertion::NotWordBoundary => {
+        let before = text[..pos].chars().next_back().is_some_and(is_word_char);
+        let after = text[pos..].chars().next().is_some_and(is_word_char);
+
+        (before != after) == (self == Assertion::WordBoundary)
+      }
+      Assertion::NotAfterWord => !text[..pos].chars().next_back().is_some_and(is_word_char),
+      Assertion::NotBeforeWord => !text[pos..].chars().next().is_some_and(is_word_char),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+  Empty,
+  Char(char),
+  Any,
+  Class(Class),
+  Assert(Assertion),
+  // The index is None for non-capturing groups.
+  Group(Box<Node>, Option<usize>),
+  Concat(Vec<Node>),
+  Alternate(Vec<Node>),
+  Repeat {
+    node: Box<Node>,
+    min: u32,
+    max: Option<u32>,
+    greedy: bool,
+  },
+}
+
+impl Node {
+  /// The number of instructions Compiler::emit turns the node into,
+  /// saturating instead of overflowing. The two have to agree.
+  fn size(&self) -> usize {
+    match self {
+      Node::Empty => 0,
+      Node::Char(_) | Node::Any | Node::Class(_) | Node::Assert(_) => 1,
+      Node::Group(node, index) => node
+        .size()
+        .saturating_add(if index.is_some() { 2 } else { 0 }),
+      Node::Concat(nodes) => nodes
+        .iter()
+        .fold(0, |size, node| size.saturating_add(node.size())),
+      Node::Alternate(nodes) => nodes.iter().fold(2 * (nodes.len() - 1), |size, node| {
+        size.saturating_add(node.size())
+      }),
+      Node::Repeat { node, min, max, .. } => {
+        let size = node.size();
+        // Each optional copy comes with a Split, a * also with a Jmp.
+        let optional = match max {
+          None => size.saturating_add(2),
+          Some(max) => ((max - min) as usize).saturating_mul(size.saturating_add(1)),
+        };
+
+        (*min as usize)
+          .saturating_mul(size)
+          .saturating_add(optional)
+      }
+    }
+  }
+}
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+  groups: usize,
+  // How many groups the parser is inside of.
+  depth: usize,
+}
+
+impl Parser {
+  fn new(pattern: &str) -> Self {
+    Self {
+      chars: pattern.chars().collect(),
+      pos: 0,
+      groups: 0,
+      depth: 0,
+    }
+  }
+
+  fn error<T>(&self, message: &str) -> Result<T, RegexError> {
+    Err(RegexError {
+      message: message.to_string(),
+      position: self.pos,
+    })
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn next(&mut self) -> Option<char> {
+    let c = self.peek();
+    self.pos += 1;
+    c
+  }
+
+  fn eat(&mut self, expected: char) -> bool {
+    if self.peek() == Some(expected) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse(mut self) -> Result<(Node, usize), RegexError> {
+    let node = self.parse_alternation()?;
+
+    if self.peek().is_some() {
+      // The only way to stop before the end is an unmatched ).
+      return self.error("unmatched )");
+    }
+
+    // Repetitions are checked as they are parsed, this catches
+    // big ones that are only too big together.
+    if node.size() > MAX_SIZE {
+      return self.error("pattern is too large");
+    }
+
+    Ok((node, self.groups))
+  }
+
+  fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+    let mut branches = vec![self.parse_concat()?];
+
+    while self.eat('|') {
+      branches.push(self.parse_concat()?);
+    }
+
+    if branches.len() == 1 {
+      Ok(branches.pop().unwrap())
+    } else {
+      Ok(Node::Alternate(branches))
+    }
+  }
+
+  fn parse_concat(&mut self) -> Result<Node, RegexError> {
+    let mut nodes = Vec::new();
+
+    while let Some(c) = self.peek() {
+      if c == '|' || c == ')' {
+        break;
+      }
+
+      nodes.push(self.parse_repeat()?);
+    }
+
+    match nodes.len() {
+      0 => Ok(Node::Empty),
+      1 => Ok(nodes.pop().unwrap()),
+      _ => Ok(Node::Concat(nodes)),
+    }
+  }
+
+  fn parse_repeat(&mut self) -> Result<Node, RegexError> {
+    let mut node = self.parse_atom()?;
+    // Each repetition of a repetition, as in a{1}{1}, is a level too.
+    let mut depth = self.depth;
+
+    loop {
+      let start = self.pos;
+      let (min, max) = match self.peek() {
+        Some('{') => match self.parse_bounds()? {
+          Some(bounds) => bounds,
+          // Not a valid repetition, the { is a literal.
+          None => break,
+        },
+        Some(c @ '*') | Some(c @ '+') | Some(c @ '?') => {
+          self.pos += 1;
+
+          match c {
+            '*' => (0, None),
+            '+' => (1, None),
+            _ => (0, Some(1)),
+          }
+        }
+        _ => break,
+      };
+
+      if let Node::Assert(_) | Node::Empty = node {
+        return self.error("nothing to repeat");
+      }
+
+      depth += 1;
+      if depth > MAX_DEPTH {
+        self.pos = start;
+        return self.error("pattern nests too deeply");
+      }
+
+      let greedy = !self.eat('?');
+
+      node = Node::Repeat {
+        node: Box::new(node),
+        min,
+        max,
+        greedy,
+      };
+
+      if node.size() > MAX_SIZE {
+        self.pos = start;
+        return self.error("repetition makes the pattern too large");
+      }
+    }
+
+    Ok(node)
+  }
+
+  /// Parses {n}, {n,} and {n,m}.
+  ///
+  /// Returns None without consuming anything if the brace
+  /// doesn't start a repetition.
+  fn parse_bounds(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+    let start = self.pos;
+    self.pos += 1;
+
+    let min = self.parse_number();
+    let max = if self.eat(',') {
+      self.parse_number()
+    } else {
+      min
+    };
+
+    if min.is_none() || !self.eat('}') {
+      self.pos = start;
+      return Ok(None);
+    }
+
+    let min = min.unwrap();
+
+    if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+      return self.error("repetition count is too large");
+    }
+
+    if max.is_some_and(|max| max < min) {
+      return self.error("invalid repetition range");
+    }
+
+    Ok(Some((min, max)))
+  }
+
+  fn parse_number(&mut self) -> Option<u32> {
+    let start = self.pos;
+
+    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+      self.pos += 1;
+    }
+
+    self.chars[start..self.pos]
+      .iter()
+      .collect::<String>()
+      .parse()
+      .ok()
+  }
+
+  fn parse_atom(&mut self) -> Result<Node, RegexError> {
+    match self.next().unwrap() {
+      '(' => {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+          self.pos -= 1;
+          return self.error("pattern nests too deeply");
+        }
+
+        let index = if self.eat('?') {
+          if !self.eat(':') {
+            return self.error("unknown group flag");
+          }
+          None
+        } else {
+          self.groups += 1;
+          Some(self.groups)
+        };
+
+        let node = self.parse_alternation()?;
+
+        if !self.eat(')') {
+          return self.error("unclosed group");
+        }
+        self.depth -= 1;
+
+        Ok(Node::Group(Box::new(node), index))
+      }
+      '[' => self.parse_class(),
+      '.' => Ok(Node::Any),
+      '^' => Ok(Node::Assert(Assertion::Start)),
+      '$' => Ok(Node::Assert(Assertion::End)),
+      '\\' => self.parse_escape(),
+      '*' | '+' | '?' => {
+        self.pos -= 1;
+        self.error("nothing to repeat")
+      }
+      c => Ok(Node::Char(c)),
+    }
+  }
+
+  fn parse_escape(&mut self) -> Result<Node, RegexError> {
+    let c = match self.next() {
+      Some(c) => c,
+      None => return self.error("trailing backslash"),
+    };
+
+    let node = match c {
+      'b' => Node::Assert(Assertion::WordBoundary),
+      'B' => Node::Assert(Assertion::NotWordBoundary),
+      _ => match self.escape_class_item(c)? {
+        ClassItem::Range(c, _) => Node::Char(c),
+        item => Node::Class(Class {
+          items: vec![item],
+          negated: false,
+        }),
+      },
+    };
+
+    Ok(node)
+  }
+
+  /// Escapes that are allowed both inside and outside of classes.
+  fn escape_class_item(&mut self, c: char) -> Result<ClassItem, RegexError> {
+    let named = |class, negated| ClassItem::Named { class, negated };
+
+    let item = match c {
+      'd' => named(Named::Digit, false),
+      'D' => named(Named::Digit, true),
+      'w' => named(Named::Word, false),
+      'W' => named(Named::Word, true),
+      's' => named(Named::Space, false),
+      'S' => named(Named::Space, true),
+      'n' => ClassItem::Range('\n', '\n'),
+      't' => ClassItem::Range('\t', '\t'),
+      'r' => ClassItem::Range('\r', '\r'),
+      c if c.is_alphanumeric() => {
+        self.pos -= 1;
+        return self.error("unknown escape");
+      }
+      c => ClassItem::Range(c, c),
+    };
+
+    Ok(item)
+  }
+
+  fn parse_class(&mut self) -> Result<Node, RegexError> {
+    let negated = self.eat('^');
+    let mut items = Vec::new();
+
+    // A ] right after the opening bracket is a literal.
+    if self.eat(']') {
+      items.push(ClassItem::Range(']', ']'));
+    }
+
+    loop {
+      let c = match self.next() {
+        Some(']') => break,
+        Some(c) => c,
+        None => return self.error("unclosed character class"),
+      };
+
+      let start = match c {
+        '[' if self.eat(':') => {
+          let name_start = self.pos;
+
+          while self.peek().is_some_and(|c| c != ':') {
+            self.pos += 1;
+          }
+
+          let name: String = self.chars[name_start..self.pos].iter().collect();
+
+          if !(self.eat(':') && self.eat(']')) {
+            return self.error("unclosed character class name");
+          }
+
+          match Named::from_posix(&name) {
+            Some(class) => items.push(ClassItem::Named {
+              class,
+              negated: false,
+            }),
+            None => return self.error("unknown character class name"),
+          }
+
+          continue;
+        }
+        '\\' => match self.next() {
+          Some(c) => match self.escape_class_item(c)? {
+            ClassItem::Range(c, _) => c,
+            item => {
+              items.push(item);
+              continue;
+            }
+          },
+          None => return self.error("trailing backslash"),
+        },
+        c => c,
+      };
+
+      // A - right before the closing bracket is a literal.
+      if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+        self.pos += 1;
+
+        let end = match self.next() {
+          Some('\\') => match self.next() {
+            Some(c) => match self.escape_class_item(c)? {
+              ClassItem::Range(c, _) => c,
+              _ => return self.error("invalid range end"),
+            },
+            None => return self.error("trailing backslash"),
+          },
+          Some(c) => c,
+          None => return self.error("unclosed character class"),
+        };
+
+        if end < start {
+          return self.error("invalid range");
+        }
+
+        items.push(ClassItem::Range(start, end));
+      } else {
+        items.push(ClassItem::Range(start, start));
+      }
+    }
+
+    Ok(Node::Class(Class { items, negated }))
+  }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+  Char(char),
+  Any,
+  Class(Class),
+  Assert(Assertion),
+  // Try the first branch before the second one.
+  Split(usize, usize),
+  Jmp(usize),
+  // Records the current position in a capture slot.
+  Save(usize),
+  Match,
+}
+
+struct Compiler {
+  insts: Vec<Inst>,
+}
+
+impl Compiler {
+  fn compile(mut self, node: &Node) -> Vec<Inst> {
+    // Slots 0 and 1 hold the bounds of the whole match.
+    self.insts.push(Inst::Save(0));
+    self.emit(node);
+    self.insts.push(Inst::Save(1));
+    self.insts.push(Inst::Match);
+
+    self.insts
+  }
+
+  fn push(&mut self, inst: Inst) -> usize {
+    self.insts.push(inst);
+    self.insts.len() - 1
+  }
+
+  fn emit(&mut self, node: &Node) {
+    match node {
+      Node::Empty => {}
+      Node::Char(c) => {
+        self.push(Inst::Char(*c));
+      }
+      Node::Any => {
+        self.push(Inst::Any);
+      }
+      Node::Class(class) => {
+        self.push(Inst::Class(class.clone()));
+      }
+      Node::Assert(assertion) => {
+        self.push(Inst::Assert(*assertion));
+      }
+      Node::Group(node, index) => match index {
+        Some(index) => {
+          self.push(Inst::Save(index * 2));
+          self.emit(node);
+          self.push(Inst::Save(index * 2 + 1));
+        }
+        None => self.emit(node),
+      },
+      Node::Concat(nodes) => {
+        for node in nodes {
+          self.emit(node);
+        }
+      }
+      Node::Alternate(nodes) => {
+        let mut jumps = Vec::with_capacity(nodes.len());
+
+        for node in &nodes[..nodes.len() - 1] {
+          let split = self.push(Inst::Split(0, 0));
+          self.emit(node);
+          jumps.push(self.push(Inst::Jmp(0)));
+
+          self.insts[split] = Inst::Split(split + 1, self.insts.len());
+        }
+
+        self.emit(&nodes[nodes.len() - 1]);
+
+        for jump in jumps {
+          self.insts[jump] = Inst::Jmp(self.insts.len());
+        }
+      }
+      Node::Repeat {
+        node,
+        min,
+        max,
+        greedy,
+      } => {
+        for _ in 0..*min {
+          self.emit(node);
+        }
+
+        match max {
+          None => {
+            let split = self.push(Inst::Split(0, 0));
+            self.emit(node);
+            self.push(Inst::Jmp(split));
+
+            self.insts[split] = self.split(split + 1, self.insts.len(), *greedy);
+          }
+          Some(max) => {
+            for _ in *min..*max {
+              let split = self.push(Inst::Split(0, 0));
+              self.emit(node);
+
+              self.insts[split] = self.split(split + 1, self.insts.len(), *greedy);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  fn split(&self, taken: usize, skipped: usize, greedy: bool) -> Inst {
+    if greedy {
+      Inst::Split(taken, skipped)
+    } else {
+      Inst::Split(skipped, taken)
+    }
+  }
+}
+
+type Slots = Vec<Option<usize>>;
+
+/// Byte ranges matched by each group of a match,
+/// group 0 being the whole match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures {
+  slots: Slots,
+}
+
+impl Captures {
+  /// Captures of a match without groups.
+  pub fn whole(start: usize, end: usize) -> Self {
+    Self {
+      slots: vec![Some(start), Some(end)],
+    }
+  }
+
+  /// None if the group didn't take part in the match or doesn't exist.
+  pub fn get(&self, group: usize) -> Option<(usize, usize)> {
+    match (self.slots.get(group * 2)?, self.slots.get(group * 2 + 1)?) {
+      (Some(start), Some(end)) => Some((*start, *end)),
+      _ => None,
+    }
+  }
+}
+
+/// The work left to do in Regex::add_thread.
+enum Frame {
+  /// Follow the instruction at this pc.
+  Follow(usize),
+  /// Put back the old value of a slot once a Save has been followed.
+  Restore(usize, Option<usize>),
+}
+
+/// The threads alive at one position of the text, in priority order.
+struct Threads {
+  visited: Vec<bool>,
+  list: Vec<(usize, Slots)>,
+  // Kept around so add_thread doesn't allocate it every time.
+  stack: Vec<Frame>,
+}
+
+impl Threads {
+  fn new(size: usize) -> Self {
+    Self {
+      visited: vec![false; size],
+      list: Vec::new(),
+      stack: Vec::new(),
+    }
+  }
+
+  fn clear(&mut self) {
+    self.visited.iter_mut().for_each(|visited| *visited = false);
+    self.list.clear();
+  }
+}
+
+/// Where a match may start and end.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Bounds {
+  #[default]
+  Anywhere,
+  /// -w: the match can't be next to a word character.
+  Word,
+  /// -x: the match must be the whole line.
+  Line,
+}
+
+/// Escapes `literal` so it matches itself.
+pub fn escape(literal: &str) -> String {
+  let mut escaped = String::with_capacity(literal.len());
+
+  for c in literal.chars() {
+    if "\\.+*?()|[]{}^$".contains(c) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+
+  escaped
+}
+
+#[derive(Debug)]
+pub struct Regex {
+  insts: Vec<Inst>,
+  slots: usize,
+  case_insensitive: bool,
+}
+
+impl Regex {
+  pub fn new(pattern: &str, case_insensitive: bool) -> Result<Self, RegexError> {
+    Self::with_bounds(pattern, case_insensitive, Bounds::Anywhere)
+  }
+
+  /// Like [Regex::new], but the matches must also respect `bounds`.
+  pub fn with_bounds(
+    pattern: &str,
+    case_insensitive: bool,
+    bounds: Bounds,
+  ) -> Result<Self, RegexError> {
+    let (node, groups) = Parser::new(pattern).parse()?;
+
+    // The pattern is grouped so an alternation inside
+    // it doesn't take the assertions as one of its branches.
+    let (before, after) = match bounds {
+      Bounds::Anywhere => (Node::Empty, Node::Empty),
+      Bounds::Word => (
+        Node::Assert(Assertion::NotAfterWord),
+        Node::Assert(Assertion::NotBeforeWord),
+      ),
+      Bounds::Line => (Node::Assert(Assertion::Start), Node::Assert(Assertion::End)),
+    };
+    let node = Node::Concat(vec![before, Node::Group(Box::new(node), None), after]);
+
+    let insts = Compiler { insts: Vec::new() }.compile(&node);
+
+    Ok(Self {
+      insts,
+      slots: (groups + 1) * 2,
+      case_insensitive,
+    })
+  }
+
+  pub fn is_match(&self, text: &str) -> bool {
+    self.find_at(text, 0).is_some()
+  }
+
+  /// Returns the byte range of the leftmost match starting at or after `start`.
+  pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+    self
+      .captures_at(text, start)
+      .and_then(|captures| captures.get(0))
+  }
+
+  /// Like [Regex::find_at], but also returns where each group matched.
+  pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+    self.exec(text, start).map(|slots| Captures { slots })
+  }
+
+  fn step(&self, inst: &Inst, c: char) -> bool {
+    match inst {
+      Inst::Char(expected) => {
+        *expected == c || (self.case_insensitive && fold(*expected) == fold(c))
+      }
+      // Lines never contain newlines, but multi-line text might.
+      Inst::Any => c != '\n',
+      Inst::Class(class) => class.matches(c, self.case_insensitive),
+      _ => false,
+    }
+  }
+
+  fn add_thread(
+    &self,
+    threads: &mut Threads,
+    pc: usize,
+    text: &str,
+    pos: usize,
+    slots: &mut Slots,
+  ) {
+    // An explicit stack instead of recursion, a long chain of
+    // splits like a{0,1000} would otherwise overflow the call stack.
+    // Frames are pushed in reverse, so the first branch of a Split
+    // and everything it leads to is followed before the second.
+    threads.stack.push(Frame::Follow(pc));
+
+    while let Some(frame) = threads.stack.pop() {
+      let pc = match frame {
+        Frame::Follow(pc) => pc,
+        Frame::Restore(slot, old) => {
+          slots[slot] = old;
+          continue;
+        }
+      };
+
+      if threads.visited[pc] {
+        continue;
+      }
+      threads.visited[pc] = true;
+
+      match self.insts[pc] {
+        Inst::Jmp(to) => threads.stack.push(Frame::Follow(to)),
+        Inst::Split(first, second) => {
+          threads.stack.push(Frame::Follow(second));
+          threads.stack.push(Frame::Follow(first));
+        }
+        Inst::Save(slot) => {
+          threads.stack.push(Frame::Restore(slot, slots[slot]));
+          slots[slot] = Some(pos);
+          threads.stack.push(Frame::Follow(pc + 1));
+        }
+        Inst::Assert(assertion) => {
+          if assertion.holds(text, pos) {
+            threads.stack.push(Frame::Follow(pc + 1));
+          }
+        }
+        _ => threads.list.push((pc, slots.clone())),
+      }
+    }
+  }
+
+  fn exec(&self, text: &str, start: usize) -> Option<Slots> {
+    let mut current = Threads::new(self.insts.len());
+    let mut next = Threads::new(self.insts.len());
+    let mut matched = None;
+    let mut pos = start;
+
+    loop {
+      // Start a new attempt at every position until something matches.
+      // It goes last so attempts that started earlier take priority.
+      if matched.is_none() {
+        let mut slots = vec![None; self.slots];
+        self.add_thread(&mut current, 0, text, pos, &mut slots);
+      }
+
+      // With no threads left there may still be a match further on,
+      // an assertion like \b can fail here and hold at the next position.
+      if current.list.is_empty() && matched.is_some() {
+        break;
+      }
+
+      let c = text[pos..].chars().next();
+
+      for (pc, mut slots) in current.list.drain(..) {
+        match &self.insts[pc] {
+          Inst::Match => {
+            matched = Some(slots);
+            // Threads after this one have lower priority.
+            break;
+          }
+          inst => {
+            if let Some(c) = c {
+              if self.step(inst, c) {
+                self.add_thread(&mut next, pc + 1, text, pos + c.len_utf8(), &mut slots);
+              }
+            }
+          }
+        }
+      }
+
+      current.clear();
+
+      match c {
+        Some(c) => pos += c.len_utf8(),
+        None => break,
+      }
+
+      std::mem::swap(&mut current, &mut next);
+    }
+
+    matched
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn find<'a>(pattern: &str, text: &'a str) -> Option<&'a str> {
+    let regex = Regex::new(pattern, false).unwrap();
+
+    regex.find_at(text, 0).map(|(start, end)| &text[start..end])
+  }
+
+  #[test]
+  fn literals_and_classes() {
+    assert_eq!(Some("duct"), find("duct", "productive"));
+    assert_eq!(Some("b2"), find("[a-c][0-9]", "xx b2"));
+    assert_eq!(Some("x"), find("[^a-c]", "abcx"));
+    assert_eq!(Some("42"), find(r"\d+", "answer: 42"));
+    assert_eq!(Some("ab"), find("[[:alpha:]]+", "  ab1"));
+    assert_eq!(None, find("[a-c]", "xyz"));
+  }
+
+  #[test]
+  fn anchors() {
+    assert_eq!(Some("Rust"), find("^Rust", "Rust:"));
+    assert_eq!(None, find("^Rust", "Trust me."));
+    assert_eq!(Some("three."), find(r"t\w+\.$", "Pick three."));
+    // \b fails at the start of the line and holds later on.
+    assert_eq!(Some("Pick"), find(r"\bP\w+", " Pick three."));
+  }
+
+  #[test]
+  fn alternation_and_groups() {
+    assert_eq!(Some("fast"), find("safe|fast", "so fast"));
+    assert_eq!(Some("abab"), find("(ab)+", "xababx"));
+    assert_eq!(Some("cd"), find("(?:ab|cd)", "xcd"));
+  }
+
+  #[test]
+  fn repetition() {
+    assert_eq!(Some("aaa"), find("a{2,3}", "aaaa"));
+    assert_eq!(Some("aa"), find("a{2}", "aaaa"));
+    assert_eq!(Some("a"), find("a+?", "aaaa"));
+    assert_eq!(Some("<a>"), find("<.*?>", "<a><b>"));
+    assert_eq!(Some("x{"), find("x{", "x{"));
+  }
+
+  #[test]
+  fn captures() {
+    let regex = Regex::new(r"(\w+)@(\w+)?\.com", false).unwrap();
+    let captures = regex.captures_at("mail bob@.com", 0).unwrap();
+
+    assert_eq!(Some((5, 13)), captures.get(0));
+    assert_eq!(Some((5, 8)), captures.get(1));
+    assert_eq!(None, captures.get(2));
+    assert_eq!(None, captures.get(3));
+  }
+
+  #[test]
+  fn case_insensitive() {
+    let regex = Regex::new("rust", true).unwrap();
+
+    assert!(regex.is_match("Trust me."));
+    assert!(!Regex::new("rust", false).unwrap().is_match("RUST"));
+  }
+
+  #[test]
+  fn invalid_patterns() {
+    assert!(Regex::new("(ab", false).is_err());
+    assert!(Regex::new("ab)", false).is_err());
+    assert!(Regex::new("[ab", false).is_err());
+    assert!(Regex::new("*a", false).is_err());
+    assert!(Regex::new("a{3,1}", false).is_err());
+  }
+
+  #[test]
+  fn size_limit() {
+    // Each repetition is fine on its own, not nested.
+    let error = Regex::new("((a{1000}){1000}){1000}", false).unwrap_err();
+    assert_eq!(
+      "repetition makes the pattern too large at position 10",
+      error.to_string()
+    );
+
+    // Only too large together.
+    let error = Regex::new(&"(a{1000}){60}".repeat(2), false).unwrap_err();
+    assert_eq!("pattern is too large at position 26", error.to_string());
+
+    // Node::size has to agree with what is compiled.
+    for pattern in [
+      "a|b(c)*",
+      "(x{2,5}?)+y?",
+      "[a-z]{3,}|^$",
+      "(?:ab|cd|e){0,4}",
+    ]
+    .iter()
+    {
+      let (node, _) = Parser::new(pattern).parse().unwrap();
+      let insts = Compiler { insts: Vec::new() }.compile(&node);
+
+      // compile adds two Saves and a Match.
+      assert_eq!(node.size() + 3, insts.len(), "{}", pattern);
+    }
+  }
+
+  #[test]
+  fn nesting_limit() {
+    let nested = |levels| format!("{}a{}", "(".repeat(levels), ")".repeat(levels));
+
+    assert!(Regex::new(&nested(MAX_DEPTH), false).unwrap().is_match("a"));
+
+    let error = Regex::new(&nested(5000), false).unwrap_err();
+    assert_eq!(
+      "pattern nests too deeply at position 250",
+      error.to_string()
+    );
+
+    let error = Regex::new(&format!("a{}", "{1}".repeat(5000)), false).unwrap_err();
+    assert_eq!(
+      "pattern nests too deeply at position 751",
+      error.to_string()
+    );
+  }
+
+  #[test]
+  fn long_chains_of_splits() {
+    // A thousand splits each leading to the next one.
+    let regex = Regex::new("(?:a?){1000}b", false).unwrap();
+
+    assert!(regex.is_match(&format!("{}b", "a".repeat(500))));
+    assert!(!regex.is_match(&"a".repeat(500)));
+  }
+}
@@ -0,0 +1,307 @@
+// --replace: rewrites every match using a template.
+//
+// The template may refer to the groups of the match: $0 is the
+// whole match, $1 the first group and so on. ${1} can be used when
+// the reference is followed by a digit, and $$ is a literal $.
+//
+// Without --in-place the changes are only printed as a unified diff.
+use std::{
+  borrow::Cow,
+  fs, io,
+  path::{Path, PathBuf},
+  process,
+};
+
+use super::Pattern;
+
+/// Lines of unchanged context around each hunk of the diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// What --replace, --in-place and --backup asked for.
+#[derive(Debug)]
+pub struct Replace {
+  pub template: Template,
+  /// --in-place: write the changes back instead of printing a diff.
+  pub in_place: bool,
+  /// --backup: keep the original file with this suffix added to its name.
+  pub backup: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+  Literal(String),
+  Group(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+  parts: Vec<Part>,
+}
+
+impl Template {
+  pub fn parse(template: &str) -> Self {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+      if c != '$' {
+        literal.push(c);
+        continue;
+      }
+
+      let braced = chars.peek() == Some(&'{');
+      if braced {
+        chars.next();
+      }
+
+      let mut digits = String::new();
+      while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(digit);
+        chars.next();
+      }
+
+      let closed = !braced || chars.peek() == Some(&'}');
+
+      match digits.parse() {
+        Ok(group) if closed => {
+          if braced {
+            chars.next();
+          }
+
+          if !literal.is_empty() {
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+          }
+          parts.push(Part::Group(group));
+        }
+        _ => {
+          // Not a reference, $$ becomes $ and anything else is kept as is.
+          if !braced && digits.is_empty() && chars.peek() == Some(&'$') {
+            chars.next();
+          }
+
+          literal.push('$');
+          if braced {
+            literal.push('{');
+          }
+          literal.push_str(&digits);
+        }
+      }
+    }
+
+    if !literal.is_empty() {
+      parts.push(Part::Literal(literal));
+    }
+
+    Self { parts }
+  }
+}
+
+/// Replaces every match in `line`.
+pub fn replace_line<'a>(pattern: &Pattern, line: &'a str, template: &Template) -> Cow<'a, str> {
+  let mut replaced = String::new();
+  // Where the text not copied to `replaced` yet starts.
+  let mut copied = 0;
+
+  for captures in pattern.captures_iter(line) {
+    let (match_start, match_end) = captures.get(0).unwrap();
+
+    replaced.push_str(&line[copied..match_start]);
+
+    for part in &template.parts {
+      match part {
+        Part::Literal(literal) => replaced.push_str(literal),
+        // Groups that didn't match are replaced with nothing.
+        Part::Group(group) => {
+          if let Some((start, end)) = captures.get(*group) {
+            replaced.push_str(&line[start..end]);
+          }
+        }
+      }
+    }
+
+    copied = match_end;
+  }
+
+  if copied == 0 && replaced.is_empty() {
+    return Cow::Borrowed(line);
+  }
+
+  replaced.push_str(&line[copied..]);
+  Cow::Owned(replaced)
+}
+
+/// Replaces every match in `contents`, keeping line terminators as they are.
+pub fn replace_all(pattern: &Pattern, contents: &str, template: &Template) -> String {
+  let mut replaced = String::with_capacity(contents.len());
+
+  for line in contents.split_inclusive('\n') {
+    let text = line.trim_end_matches('\n').trim_end_matches('\r');
+
+    replaced.push_str(&replace_line(pattern, text, template));
+    replaced.push_str(&line[text.len()..]);
+  }
+
+  replaced
+}
+
+/// Renders the changes from `old` to `new` as a unified diff.
+///
+/// Replacing never adds or removes lines, Config rejects templates
+/// with a newline and matches never span lines, so line i of `old`
+/// is always compared with line i of `new`.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+  let old: Vec<&str> = old.lines().collect();
+  let new: Vec<&str> = new.lines().collect();
+
+  let changed: Vec<usize> = (0..old.len()).filter(|&i| old[i] != new[i]).collect();
+
+  if changed.is_empty() {
+    return String::new();
+  }
+
+  let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+  let mut i = 0;
+
+  while i < changed.len() {
+    // Changes closer than twice the context share a hunk.
+    let mut j = i;
+    while j + 1 < changed.len() && changed[j + 1] - changed[j] <= DIFF_CONTEXT * 2 + 1 {
+      j += 1;
+    }
+
+    let start = changed[i].saturating_sub(DIFF_CONTEXT);
+    let end = (changed[j] + DIFF_CONTEXT + 1).min(old.len());
+
+    diff.push_str(&format!(
+      "@@ -{},{} +{},{} @@\n",
+      start + 1,
+      end - start,
+      start + 1,
+      end - start
+    ));
+
+    for line in start..end {
+      if old[line] == new[line] {
+        diff.push_str(&format!(" {}\n", old[line]));
+      } else {
+        diff.push_str(&format!("-{}\n+{}\n", old[line], new[line]));
+      }
+    }
+
+    i = j + 1;
+  }
+
+  diff
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.as_os_str().to_os_string();
+  name.push(suffix);
+  PathBuf::from(name)
+}
+
+/// Replaces the contents of `path` with `contents`.
+///
+/// The new contents are written to a temporary file next to `path`
+/// which is then renamed over it, so the file is never left half written.
+/// With `backup`, the original file is kept with that suffix added to its name.
+pub fn write_in_place(path: &Path, contents: &str, backup: Option<&str>) -> io::Result<()> {
+  let name = path
+    .file_name()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+
+  let mut temp_name = std::ffi::OsString::from(".");
+  temp_name.push(name);
+  temp_name.push(format!(".grep-{}.tmp", process::id()));
+  let temp = path.with_file_name(temp_name);
+
+  let write = || -> io::Result<()> {
+    fs::write(&temp, contents)?;
+    fs::set_permissions(&temp, fs::metadata(path)?.permissions())?;
+    fs::File::open(&temp)?.sync_all()?;
+
+    if let Some(suffix) = backup {
+      fs::copy(path, with_suffix(path, suffix))?;
+    }
+
+    fs::rename(&temp, path)
+  };
+
+  write().inspect_err(|_| {
+    let _ = fs::remove_file(&temp);
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::grep::Regex;
+
+  fn regex(pattern: &str) -> Pattern {
+    Pattern::Regex(Regex::new(pattern, false).unwrap())
+  }
+
+  #[test]
+  fn templates() {
+    let pattern = regex(r"(\w+)@(\w+)\.com");
+
+    let replaced = replace_line(
+      &pattern,
+      "bob@example.com, amy@test.com",
+      &Template::parse("$2 has ${1}0 for $$5"),
+    );
+
+    assert_eq!("example has bob0 for $5, test has amy0 for $5", replaced);
+  }
+
+  #[test]
+  fn empty_matches() {
+    let replaced = replace_line(&regex("x*"), "abc", &Template::parse("-"));
+
+    assert_eq!("-a-b-c-", replaced);
+  }
+
+  #[test]
+  fn diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+    let new = replace_all(&regex("^[bk]$"), old, &Template::parse("X"));
+
+    assert_eq!(
+      "\
+--- a/file
++++ b/file
+@@ -1,5 +1,5 @@
+ a
+-b
++X
+ c
+ d
+ e
+@@ -8,5 +8,5 @@
+ h
+ i
+ j
+-k
++X
+ l
+",
+      unified_diff("file", old, &new)
+    );
+  }
+
+  #[test]
+  fn in_place_with_backup() {
+    let path = std::env::temp_dir().join(format!("grep-replace-{}.txt", process::id()));
+    fs::write(&path, "old\n").unwrap();
+
+    write_in_place(&path, "new\n", Some(".bak")).unwrap();
+
+    let backup = with_suffix(&path, ".bak");
+    assert_eq!("new\n", fs::read_to_string(&path).unwrap());
+    assert_eq!("old\n", fs::read_to_string(&backup).unwrap());
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&backup).unwrap();
+  }
+}
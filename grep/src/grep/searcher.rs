@@ -0,0 +1,262 @@
+// Feeds lines to a pattern one at a time, so the whole input
+// never has to be held in memory as a String.
+//
+// Regular files are memory mapped and searched as one big slice,
+// the operating system pages them in and out as needed.
+// Everything else, like stdin and pipes, is read line by line.
+//
+// Lines that are not valid UTF-8 are searched and printed
+// with the invalid bytes replaced by U+FFFD instead of
+// aborting the whole search.
+use std::{
+  borrow::Cow,
+  collections::VecDeque,
+  fs::File,
+  io::{self, BufRead},
+};
+
+use memmap2::Mmap;
+
+use super::Pattern;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+  Match,
+  // Printed because of -A, -B or -C.
+  Context,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Line<'a> {
+  // Line numbers start at 1.
+  pub number: usize,
+  pub text: Cow<'a, str>,
+  pub kind: LineKind,
+  // Index of the query that matched a selected line.
+  // Always None for context lines and with -v.
+  pub pattern: Option<usize>,
+}
+
+/// Options that change which lines are selected.
+#[derive(Debug, Default, Clone)]
+pub struct SearchOptions {
+  /// -v: select the lines that don't match.
+  pub invert: bool,
+  /// -B: lines of context before each match.
+  pub before: usize,
+  /// -A: lines of context after each match.
+  pub after: usize,
+  /// -m: stop after this many selected lines.
+  pub max_count: Option<usize>,
+}
+
+impl SearchOptions {
+  pub fn has_context(&self) -> bool {
+    self.before > 0 || self.after > 0
+  }
+}
+
+/// Decides which lines are selected and which are context.
+struct Selector<'a, 'o> {
+  options: &'o SearchOptions,
+  // Lines seen since the last line we returned, they may become
+  // context for the next match.
+  before: VecDeque<Line<'a>>,
+  after: usize,
+  selected: usize,
+}
+
+impl<'a, 'o> Selector<'a, 'o> {
+  fn new(options: &'o SearchOptions) -> Self {
+    Self {
+      options,
+      before: VecDeque::with_capacity(options.before),
+      after: 0,
+      selected: 0,
+    }
+  }
+
+  fn max_reached(&self) -> bool {
+    self
+      .options
+      .max_count
+      .is_some_and(|max| self.selected >= max)
+  }
+
+  /// True once no line that comes next can be printed.
+  fn done(&self) -> bool {
+    self.max_reached() && self.after == 0
+  }
+
+  fn push(
+    &mut self,
+    number: usize,
+    text: Cow<'a, str>,
+    pattern: &Pattern,
+    sink: &mut dyn FnMut(Line<'a>) -> io::Result<()>,
+  ) -> io::Result<()> {
+    let matched = pattern.find(&text);
+
+    if !self.max_reached() && matched.is_some() != self.options.invert {
+      self.selected += 1;
+
+      for line in self.before.drain(..) {
+        sink(line)?;
+      }
+
+      sink(Line {
+        number,
+        text,
+        kind: LineKind::Match,
+        pattern: matched,
+      })?;
+
+      self.after = self.options.after;
+    } else if self.after > 0 {
+      sink(Line {
+        number,
+        text,
+        kind: LineKind::Context,
+        pattern: None,
+      })?;
+
+      self.after -= 1;
+    } else if self.options.before > 0 {
+      if self.before.len() == self.options.before {
+        self.before.pop_front();
+      }
+
+      self.before.push_back(Line {
+        number,
+        text,
+        kind: LineKind::Context,
+        pattern: None,
+      });
+    }
+
+    Ok(())
+  }
+}
+
+/// Strips the line terminator, \n or \r\n like str::lines does.
+fn trim_line(line: &[u8]) -> &[u8] {
+  let line = line.strip_suffix(b"\n").unwrap_or(line);
+  line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+pub struct Searcher<'p> {
+  pattern: &'p Pattern,
+  options: &'p SearchOptions,
+}
+
+impl<'p> Searcher<'p> {
+  pub fn new(pattern: &'p Pattern, options: &'p SearchOptions) -> Self {
+    Self { pattern, options }
+  }
+
+  /// Searches `contents`, passing selected and context lines to `sink` in order.
+  pub fn search_slice<'a>(
+    &self,
+    contents: &'a [u8],
+    sink: &mut dyn FnMut(Line<'a>) -> io::Result<()>,
+  ) -> io::Result<()> {
+    let mut selector = Selector::new(self.options);
+
+    // split_inclusive doesn't yield an empty line after the last \n.
+    for (i, line) in contents.split_inclusive(|&b| b == b'\n').enumerate() {
+      if selector.done() {
+        break;
+      }
+
+      let text = String::from_utf8_lossy(trim_line(line));
+      selector.push(i + 1, text, self.pattern, sink)?;
+    }
+
+    Ok(())
+  }
+
+  /// Same as [Searcher::search_slice] but reads one line at a time.
+  pub fn search_reader(
+    &self,
+    mut reader: impl BufRead,
+    sink: &mut dyn FnMut(Line<'static>) -> io::Result<()>,
+  ) -> io::Result<()> {
+    let mut selector = Selector::new(self.options);
+    let mut buffer = Vec::new();
+    let mut number = 0;
+
+    while !selector.done() {
+      buffer.clear();
+
+      if reader.read_until(b'\n', &mut buffer)? == 0 {
+        break;
+      }
+
+      number += 1;
+
+      // Owned because the buffer is reused for the next line
+      // while this one may still be waiting as context.
+      let text = String::from_utf8_lossy(trim_line(&buffer)).into_owned();
+      selector.push(number, Cow::Owned(text), self.pattern, sink)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Maps `file` into memory if it is a regular, non empty, file.
+///
+/// Returns None when the file can't be mapped, the caller should
+/// read it with [Searcher::search_reader] instead.
+pub fn mmap(file: &File) -> Option<Mmap> {
+  let metadata = file.metadata().ok()?;
+
+  if !metadata.is_file() || metadata.len() == 0 {
+    return None;
+  }
+
+  // SAFETY: the map is only read from. If another process truncates
+  // the file while it is mapped we may crash, like every other tool
+  // that maps files, but that's a price worth paying to search huge
+  // files without copying them.
+  unsafe { Mmap::map(file).ok() }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reader_and_slice_select_the_same_lines() {
+    let pattern = Pattern::Fixed {
+      query: "b".to_string(),
+      case_insensitive: false,
+    };
+    let options = SearchOptions {
+      before: 1,
+      ..SearchOptions::default()
+    };
+    let searcher = Searcher::new(&pattern, &options);
+
+    let contents = b"a\r\nb\nc\n\xffb\n";
+
+    let mut from_slice = Vec::new();
+    searcher
+      .search_slice(contents, &mut |line| {
+        from_slice.push(line.text.into_owned());
+        Ok(())
+      })
+      .unwrap();
+
+    let mut from_reader = Vec::new();
+    searcher
+      .search_reader(&contents[..], &mut |line| {
+        from_reader.push(line.text.into_owned());
+        Ok(())
+      })
+      .unwrap();
+
+    assert_eq!(vec!["a", "b", "c", "\u{fffd}b"], from_slice);
+    assert_eq!(from_slice, from_reader);
+  }
+}
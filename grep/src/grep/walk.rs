@@ -0,0 +1,380 @@
+// Turns the paths given on the command line into the list of files to search.
+//
+// Directories are searched recursively. Hidden entries and entries
+// matched by .gitignore or .ignore files are skipped, the rules in
+// the ignore files of a directory apply to everything below it.
+//
+// Symbolic links to directories are only followed with -R, and then
+// every directory is visited once, so a link like d/up -> .. doesn't
+// send the walk around in circles.
+use std::{
+  collections::HashSet,
+  fs::{self, Metadata},
+  path::{Path, PathBuf},
+};
+
+use super::{glob::Glob, Error};
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// The path that stands for stdin.
+pub const STDIN: &str = "-";
+
+/// --include and --exclude globs, and whether -R was given.
+#[derive(Debug, Default)]
+pub struct Filter {
+  pub include: Vec<Glob>,
+  pub exclude: Vec<Glob>,
+  /// -R: descend into symbolic links to directories.
+  pub follow_links: bool,
+}
+
+impl Filter {
+  fn allows(&self, path: &str) -> bool {
+    (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(path)))
+      && !self.exclude.iter().any(|glob| glob.is_match(path))
+  }
+}
+
+struct Rule {
+  glob: Glob,
+  // Lines starting with ! re-include what an earlier rule ignored.
+  negated: bool,
+  // Lines ending with / only match directories.
+  dir_only: bool,
+}
+
+/// The rules in the ignore files of one directory.
+struct Ignore {
+  dir: PathBuf,
+  rules: Vec<Rule>,
+}
+
+impl Ignore {
+  fn from_dir(dir: &Path) -> Option<Self> {
+    let mut rules = Vec::new();
+
+    for name in IGNORE_FILES.iter() {
+      let contents = match fs::read_to_string(dir.join(name)) {
+        Ok(contents) => contents,
+        Err(_) => continue,
+      };
+
+      for line in contents.lines() {
+        let line = line.trim_end();
+
+        if line.is_empty() || line.starts_with('#') {
+          continue;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+          Some(line) => (true, line),
+          None => (false, line),
+        };
+
+        let (dir_only, line) = match line.strip_suffix('/') {
+          Some(line) => (true, line),
+          None => (false, line),
+        };
+
+        // Invalid lines are skipped like git does.
+        if let Ok(glob) = Glob::new(line) {
+          rules.push(Rule {
+            glob,
+            negated,
+            dir_only,
+          });
+        }
+      }
+    }
+
+    if rules.is_empty() {
+      None
+    } else {
+      Some(Self {
+        dir: dir.to_path_buf(),
+        rules,
+      })
+    }
+  }
+
+  /// Some(true) if `path` is ignored, Some(false) if a negated rule
+  /// re-includes it and None if no rule says anything about it.
+  fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+    let relative = relative_path(&self.dir, path);
+
+    // The last rule that matches wins.
+    self
+      .rules
+      .iter()
+      .rev()
+      .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(&relative))
+      .map(|rule| !rule.negated)
+  }
+}
+
+fn relative_path(base: &Path, path: &Path) -> String {
+  let relative = path.strip_prefix(base).unwrap_or(path);
+
+  relative
+    .components()
+    .map(|component| component.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// What identifies a directory however it is reached, None where
+/// there's no such thing, in which case links aren't followed.
+#[cfg(unix)]
+fn dir_id(metadata: &Metadata) -> Option<(u64, u64)> {
+  use std::os::unix::fs::MetadataExt;
+
+  Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+  None
+}
+
+fn is_hidden(path: &Path) -> bool {
+  path
+    .file_name()
+    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Returns the files to search, in the order they should be searched,
+/// and the paths that couldn't be read.
+///
+/// Files named on the command line are always searched,
+/// the filters only apply to files found inside directories.
+pub fn files(paths: &[String], filter: &Filter) -> (Vec<PathBuf>, Vec<Error>) {
+  let mut files = Vec::new();
+  let mut errors = Vec::new();
+
+  for path in paths {
+    let path = Path::new(path);
+
+    if path == Path::new(STDIN) {
+      files.push(path.to_path_buf());
+      continue;
+    }
+
+    match fs::metadata(path) {
+      Ok(metadata) if metadata.is_dir() => {
+        let mut ignores = Vec::new();
+        let mut visited = HashSet::new();
+        visited.extend(dir_id(&metadata));
+        visit(
+          path,
+          path,
+          filter,
+          &mut ignores,
+          &mut visited,
+          &mut files,
+          &mut errors,
+        );
+      }
+      Ok(_) => files.push(path.to_path_buf()),
+      Err(error) => errors.push(Error::Path {
+        path: path.to_path_buf(),
+        error,
+      }),
+    }
+  }
+
+  (files, errors)
+}
+
+fn visit(
+  root: &Path,
+  dir: &Path,
+  filter: &Filter,
+  ignores: &mut Vec<Ignore>,
+  visited: &mut HashSet<(u64, u64)>,
+  files: &mut Vec<PathBuf>,
+  errors: &mut Vec<Error>,
+) {
+  // Sorted so the output doesn't depend on the file system.
+  let entries = fs::read_dir(dir).and_then(|entries| {
+    entries
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect::<Result<Vec<_>, _>>()
+  });
+
+  let mut entries = match entries {
+    Ok(entries) => entries,
+    Err(error) => {
+      errors.push(Error::Path {
+        path: dir.to_path_buf(),
+        error,
+      });
+      return;
+    }
+  };
+  entries.sort();
+
+  let ignore = Ignore::from_dir(dir);
+  let pushed = ignore.is_some();
+  ignores.extend(ignore);
+
+  for path in entries {
+    if is_hidden(&path) {
+      continue;
+    }
+
+    // symlink_metadata doesn't follow links, so links are told apart
+    // from what they point to.
+    let metadata = fs::symlink_metadata(&path).and_then(|metadata| {
+      if metadata.file_type().is_symlink() {
+        fs::metadata(&path).map(|target| (true, target))
+      } else {
+        Ok((false, metadata))
+      }
+    });
+
+    let (is_link, metadata) = match metadata {
+      Ok(metadata) => metadata,
+      Err(error) => {
+        errors.push(Error::Path { path, error });
+        continue;
+      }
+    };
+    let is_dir = metadata.is_dir();
+
+    if is_dir && is_link && !filter.follow_links {
+      continue;
+    }
+
+    // Rules from the innermost directory take precedence.
+    let ignored = ignores
+      .iter()
+      .rev()
+      .find_map(|ignore| ignore.matched(&path, is_dir))
+      .unwrap_or(false);
+
+    if ignored {
+      continue;
+    }
+
+    if is_dir {
+      // Links are only followed where directories can be told apart.
+      let first_visit = match dir_id(&metadata) {
+        Some(id) => visited.insert(id),
+        None => !is_link,
+      };
+
+      if first_visit {
+        visit(root, &path, filter, ignores, visited, files, errors);
+      }
+    } else if filter.allows(&relative_path(root, &path)) {
+      files.push(path);
+    }
+  }
+
+  if pushed {
+    ignores.pop();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn names(root: &Path, filter: &Filter) -> Vec<String> {
+    let (files, errors) = files(&[root.to_string_lossy().to_string()], filter);
+    assert!(errors.is_empty());
+
+    files.iter().map(|path| relative_path(root, path)).collect()
+  }
+
+  #[test]
+  fn walks_directories_skipping_hidden_and_ignored_entries() {
+    let root = std::env::temp_dir().join(format!("grep-walk-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    for dir in ["src/nested", "target", ".git"].iter() {
+      fs::create_dir_all(root.join(dir)).unwrap();
+    }
+
+    for file in [
+      "a.txt",
+      "b.log",
+      "keep.log",
+      ".hidden",
+      "src/main.rs",
+      "src/nested/notes.txt",
+      "target/out.txt",
+      ".git/config",
+    ]
+    .iter()
+    {
+      fs::write(root.join(file), "").unwrap();
+    }
+
+    fs::write(root.join(".gitignore"), "target/\n*.log\n!keep.log\n").unwrap();
+    fs::write(root.join("src/.ignore"), "nested\n").unwrap();
+
+    assert_eq!(
+      vec!["a.txt", "keep.log", "src/main.rs"],
+      names(&root, &Filter::default())
+    );
+
+    let filter = Filter {
+      include: vec![Glob::new("*.txt").unwrap(), Glob::new("*.rs").unwrap()],
+      exclude: vec![Glob::new("src/*").unwrap()],
+      ..Filter::default()
+    };
+    assert_eq!(vec!["a.txt"], names(&root, &filter));
+
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinked_directories_are_only_followed_with_follow_links() {
+    use std::os::unix::fs::symlink;
+
+    let root = std::env::temp_dir().join(format!("grep-walk-links-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    fs::create_dir_all(root.join("d")).unwrap();
+    fs::create_dir_all(root.join("elsewhere")).unwrap();
+    fs::write(root.join("d/a.txt"), "").unwrap();
+    fs::write(root.join("elsewhere/b.txt"), "").unwrap();
+
+    // A loop back to the root, a link to a directory seen anyway
+    // and a link to a file, which is searched like the file.
+    symlink("..", root.join("d/up")).unwrap();
+    symlink("../elsewhere", root.join("d/other")).unwrap();
+    symlink("a.txt", root.join("d/link.txt")).unwrap();
+
+    assert_eq!(
+      vec!["d/a.txt", "d/link.txt", "elsewhere/b.txt"],
+      names(&root, &Filter::default())
+    );
+
+    let filter = Filter {
+      follow_links: true,
+      ..Filter::default()
+    };
+    assert_eq!(
+      vec!["d/a.txt", "d/link.txt", "d/other/b.txt"],
+      names(&root, &filter)
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn missing_paths_are_errors() {
+    let paths = vec!["Cargo.toml".to_string(), "no-such-file".to_string()];
+    let (files, errors) = files(&paths, &Filter::default());
+
+    assert_eq!(vec![PathBuf::from("Cargo.toml")], files);
+    assert!(matches!(
+      &errors[..],
+      [Error::Path { path, .. }] if path == Path::new("no-such-file")
+    ));
+  }
+}
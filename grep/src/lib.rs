@@ -0,0 +1,5 @@
+// The search itself lives in a library so it can be used,
+// and tested, without going through the command line.
+mod grep;
+
+pub use crate::grep::*;
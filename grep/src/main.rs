@@ -1,9 +1,10 @@
 // grep - Globally search a Regular Expression and Print
 use std::{env, process};
-mod grep;
 
 use grep::{Config, ConfigError};
 
+const HELP_HINT: &str = "Try 'grep --help' for more information.";
+
 fn main() {
   let args: Vec<String> = env::args().collect();
 
@@ -11,14 +12,69 @@ fn main() {
     match err {
       ConfigError::NotEnoughArguments { expected, got } => {
         // eprintln! prints to stderr.
-        eprintln!("expected {} arguments, got {}", expected, got);
-        process::exit(1);
+        eprintln!(
+          "expected {} arguments, got {}\n{}",
+          expected, got, HELP_HINT
+        );
+        process::exit(2);
+      }
+      ConfigError::MissingValue { flag } => {
+        eprintln!("{} expects a value\n{}", flag, HELP_HINT);
+        process::exit(2);
+      }
+      ConfigError::InvalidNumber { flag, value } => {
+        eprintln!("{} expects a number, got {}", flag, value);
+        process::exit(2);
+      }
+      ConfigError::InvalidPattern(err) => {
+        eprintln!("invalid pattern: {}", err);
+        process::exit(2);
+      }
+      ConfigError::PatternFile { path, error } => {
+        eprintln!("can't read patterns from {}: {}", path, error);
+        process::exit(2);
+      }
+      ConfigError::InvalidGlob { glob, error } => {
+        eprintln!("invalid glob {}: {}", glob, error);
+        process::exit(2);
+      }
+      ConfigError::InvalidColor { value } => {
+        eprintln!("--color expects auto, always or never, got {}", value);
+        process::exit(2);
+      }
+      ConfigError::TemplateNewline { template } => {
+        eprintln!(
+          "--replace can't add lines, the template {:?} has a newline",
+          template
+        );
+        process::exit(2);
+      }
+      ConfigError::UnknownFlag { flag } => {
+        eprintln!("unknown flag {}\n{}", flag, HELP_HINT);
+        process::exit(2);
+      }
+      ConfigError::UnexpectedValue { flag } => {
+        eprintln!("{} doesn't take a value\n{}", flag, HELP_HINT);
+        process::exit(2);
+      }
+      ConfigError::Help => {
+        print!("{}", grep::help());
+        process::exit(0);
+      }
+      ConfigError::Version => {
+        println!("grep {}", grep::VERSION);
+        process::exit(0);
       }
     };
   });
 
-  if let Err(e) = grep::run(config) {
-    eprintln!("Application error: {}", e);
-    process::exit(1);
+  // Like system grep: 0 if a line was selected, 1 if none
+  // was and 2 if something went wrong.
+  match grep::run(config) {
+    Ok(outcome) => process::exit(outcome.exit_code()),
+    Err(e) => {
+      eprintln!("grep: {}", e);
+      process::exit(2);
+    }
   }
 }
@@ -0,0 +1,39 @@
+use std::{
+  io::{BufRead, BufReader, Write},
+  process::{Command, Stdio},
+  sync::mpsc,
+  thread,
+  time::Duration,
+};
+
+#[test]
+fn matches_on_stdin_are_printed_before_it_ends() {
+  let mut grep = Command::new(env!("CARGO_BIN_EXE_grep"))
+    .arg("foo")
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap();
+
+  let mut stdin = grep.stdin.take().unwrap();
+  let stdout = grep.stdout.take().unwrap();
+
+  // stdin stays open, the line can only come back if it isn't
+  // held until the end of the input.
+  stdin.write_all(b"foo\nbar\n").unwrap();
+
+  let (sender, receiver) = mpsc::channel();
+  thread::spawn(move || {
+    let mut line = String::new();
+    BufReader::new(stdout).read_line(&mut line).unwrap();
+    sender.send(line).unwrap();
+  });
+
+  let line = receiver.recv_timeout(Duration::from_secs(10));
+
+  drop(stdin);
+  let status = grep.wait().unwrap();
+
+  assert_eq!(Ok("foo\n".to_string()), line);
+  assert!(status.success());
+}
@@ -1,8 +1,9 @@
-use multithreaded_web_server::ThreadPool;
+use multithreaded_web_server::{Method, Request, Response, Router, ThreadPool};
 use std::fs;
 use std::io::prelude::*;
 use std::io::Read;
 use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
 /// The two main protocols involved in web servers are the
 /// Hypertext Transfer Protocol(HTTP) and the
 /// Transmission Control Protocol(TCP).
@@ -20,42 +21,40 @@ use std::net::{TcpListener, TcpStream};
 /// HTTP builds on top of TCP by defining the contents of the requests
 /// and responses. It's technically possible to use HTTP with other protocols,
 /// but in the vast majority of cases, HTTP sends its data over TCP.
-
 fn main() {
   let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
   let pool = ThreadPool::new(4);
 
+  let mut router = Router::new();
+  router.add(Method::Get, "/", |_| {
+    Response::ok(fs::read_to_string("hello.html").unwrap())
+  });
+  router.not_found(|_| Response::not_found(fs::read_to_string("404.html").unwrap()));
+
+  // Every connection is handled on the pool, and they all share the router.
+  let router = Arc::new(router);
+
   for stream in listener.incoming() {
     let stream = stream.unwrap();
+    let router = Arc::clone(&router);
 
-    pool.execute(|| {
-      handle_connection(stream);
+    pool.execute(move || {
+      handle_connection(stream, &router);
     });
   }
 }
 
-fn handle_connection(mut stream: TcpStream) {
+fn handle_connection(mut stream: TcpStream, router: &Router) {
   let mut buffer = [0; 1024];
 
-  stream.read(&mut buffer).unwrap();
+  let bytes_read = stream.read(&mut buffer).unwrap();
+  let request = &buffer[..bytes_read];
 
-  let get = b"GET / HTTP/1.1\r\n";
-
-  let (status_line, filename) = if buffer.starts_with(get) {
-    ("HTTP/1.1 200 OK", "hello.html")
-  } else {
-    ("HTTP/1.1 404 NOT FOUND", "404.html")
+  let response = match Request::parse(request) {
+    Some(request) => router.handle(request),
+    None => Response::bad_request("Bad Request"),
   };
 
-  let contents = fs::read_to_string(filename).unwrap();
-
-  let response = format!(
-    "{}\r\nContent-Length: {}\r\n\r\n{}",
-    status_line,
-    contents.len(),
-    contents
-  );
-
-  stream.write(response.as_bytes()).unwrap();
+  stream.write_all(response.to_http().as_bytes()).unwrap();
   stream.flush().unwrap();
 }
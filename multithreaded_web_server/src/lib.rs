@@ -1,7 +1,14 @@
-use std::{
-  sync::{mpsc, Arc, Mutex},
-  thread,
-};
+use std::{sync::Arc, thread, time::Duration};
+
+mod queue;
+mod router;
+mod scheduler;
+
+use queue::JobQueue;
+pub use queue::Priority;
+pub use router::{Method, Request, Response, Router};
+use scheduler::Scheduler;
+pub use scheduler::{Clock, ScheduleHandle, SystemClock, VirtualClock};
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
@@ -14,7 +21,9 @@ enum Message {
 
 pub struct ThreadPool {
   workers: Vec<Worker>,
-  sender: mpsc::Sender<Message>,
+  queue: Arc<JobQueue>,
+  // Option so Drop can stop the timer thread before the workers.
+  scheduler: Option<Scheduler>,
 }
 
 impl ThreadPool {
@@ -27,25 +36,77 @@ impl ThreadPool {
   /// The `new` function will panic if the size is 0
   /// or greater than 1000.
   pub fn new(size: usize) -> Self {
+    Self::with_clock(size, Arc::new(SystemClock::new()))
+  }
+
+  /// Create a new ThreadPool whose scheduled jobs follow `clock`.
+  ///
+  /// Passing a [VirtualClock] makes [ThreadPool::schedule_after] and
+  /// [ThreadPool::schedule_every] deterministic in tests.
+  ///
+  /// # Panics
+  ///
+  /// Same as [ThreadPool::new].
+  pub fn with_clock(size: usize, clock: Arc<dyn Clock>) -> Self {
     // NOTE: new does not return Result<T, E> for simplicity.
     assert!(size > 0 && size <= 1000);
 
-    let (sender, receiver) = mpsc::channel();
-
-    let receiver = Arc::new(Mutex::new(receiver));
+    let queue = Arc::new(JobQueue::new());
 
     let mut workers = Vec::with_capacity(size);
     for id in 0..size {
-      workers.push(Worker::new(id, Arc::clone(&receiver)));
+      workers.push(Worker::new(id, Arc::clone(&queue)));
     }
 
-    Self { workers, sender }
+    let scheduler = Scheduler::new(clock, Arc::clone(&queue));
+
+    Self {
+      workers,
+      queue,
+      scheduler: Some(scheduler),
+    }
   }
 
+  /// Runs `f` on the pool with [Priority::Normal].
   pub fn execute(&self, f: impl FnOnce() + Send + 'static) {
+    self.execute_with_priority(Priority::Normal, f);
+  }
+
+  /// Runs `f` on the pool, queueing it in the `priority` lane.
+  ///
+  /// Jobs in higher priority lanes are dequeued first, but lower
+  /// priority lanes still get a share of every round so they are
+  /// never starved.
+  pub fn execute_with_priority(&self, priority: Priority, f: impl FnOnce() + Send + 'static) {
     let job = Box::new(f);
 
-    self.sender.send(Message::NewJob(job)).unwrap();
+    self.queue.push(priority, job);
+  }
+
+  /// Runs `f` on the pool once `delay` has passed.
+  pub fn schedule_after(
+    &self,
+    delay: Duration,
+    f: impl FnOnce() + Send + 'static,
+  ) -> ScheduleHandle {
+    self.scheduler.as_ref().unwrap().once(delay, f)
+  }
+
+  /// Runs `f` on the pool every `interval`, starting one `interval` from now,
+  /// until the returned handle is cancelled or the pool is dropped.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `interval` is zero, the job would be due again
+  /// as soon as it was rescheduled.
+  pub fn schedule_every(
+    &self,
+    interval: Duration,
+    f: impl Fn() + Send + Sync + 'static,
+  ) -> ScheduleHandle {
+    assert!(interval > Duration::ZERO, "the interval must not be zero");
+
+    self.scheduler.as_ref().unwrap().every(interval, f)
   }
 }
 
@@ -53,9 +114,13 @@ impl Drop for ThreadPool {
   fn drop(&mut self) {
     println!("Terminating workers");
 
-    for _ in &self.workers {
-      self.sender.send(Message::Terminate).unwrap();
-    }
+    // Stop the timer thread first so it doesn't queue jobs
+    // nobody is going to run.
+    drop(self.scheduler.take());
+
+    // Workers finish the jobs that are already queued
+    // before they see the queue is closed.
+    self.queue.close();
 
     for worker in &mut self.workers {
       println!("Stopping workers");
@@ -74,9 +139,9 @@ struct Worker {
 }
 
 impl Worker {
-  fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Self {
+  fn new(id: usize, queue: Arc<JobQueue>) -> Self {
     let thread = thread::spawn(move || loop {
-      let message = receiver.lock().unwrap().recv().unwrap();
+      let message = queue.pop();
 
       match message {
         Message::NewJob(job) => {
@@ -0,0 +1,187 @@
+use std::{
+  collections::VecDeque,
+  sync::{Condvar, Mutex},
+};
+
+use crate::{Job, Message};
+
+/// The lane a job is queued in.
+///
+/// Lanes are served by weighted round robin: every lane gets
+/// `weight` dequeues per round, and higher priority lanes are served
+/// first within a round. This means a busy `High` lane can't starve
+/// the `Low` lane, it only makes it wait for its turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+  High,
+  Normal,
+  Low,
+}
+
+impl Priority {
+  const LANES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+  fn index(self) -> usize {
+    match self {
+      Priority::High => 0,
+      Priority::Normal => 1,
+      Priority::Low => 2,
+    }
+  }
+
+  /// How many jobs the lane may hand out per round.
+  fn weight(self) -> usize {
+    match self {
+      Priority::High => 4,
+      Priority::Normal => 2,
+      Priority::Low => 1,
+    }
+  }
+}
+
+struct Lanes {
+  jobs: [VecDeque<Job>; 3],
+  // How many jobs each lane can still hand out in the current round.
+  credits: [usize; 3],
+  terminating: bool,
+}
+
+impl Lanes {
+  fn pop(&mut self) -> Option<Job> {
+    // Two passes at most: if no lane with pending jobs has credits left,
+    // the round is over and every lane gets its credits back.
+    for _ in 0..2 {
+      for lane in Priority::LANES.iter() {
+        let i = lane.index();
+
+        if self.credits[i] > 0 {
+          if let Some(job) = self.jobs[i].pop_front() {
+            self.credits[i] -= 1;
+            return Some(job);
+          }
+        }
+      }
+
+      for lane in Priority::LANES.iter() {
+        self.credits[lane.index()] = lane.weight();
+      }
+    }
+
+    None
+  }
+}
+
+/// The queue shared by the pool and its workers.
+///
+/// Replaces the single mpsc channel so that workers can choose
+/// which lane to take the next job from.
+pub(crate) struct JobQueue {
+  lanes: Mutex<Lanes>,
+  available: Condvar,
+}
+
+impl JobQueue {
+  pub(crate) fn new() -> Self {
+    Self {
+      lanes: Mutex::new(Lanes {
+        jobs: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+        credits: [
+          Priority::High.weight(),
+          Priority::Normal.weight(),
+          Priority::Low.weight(),
+        ],
+        terminating: false,
+      }),
+      available: Condvar::new(),
+    }
+  }
+
+  pub(crate) fn push(&self, priority: Priority, job: Job) {
+    let mut lanes = self.lanes.lock().unwrap();
+
+    lanes.jobs[priority.index()].push_back(job);
+
+    self.available.notify_one();
+  }
+
+  /// Blocks until there is a job to run.
+  ///
+  /// Returns [Message::Terminate] once the queue has been closed
+  /// and every queued job has been handed out, so jobs submitted
+  /// before the pool is dropped still run.
+  pub(crate) fn pop(&self) -> Message {
+    let mut lanes = self.lanes.lock().unwrap();
+
+    loop {
+      if let Some(job) = lanes.pop() {
+        return Message::NewJob(job);
+      }
+
+      if lanes.terminating {
+        return Message::Terminate;
+      }
+
+      lanes = self.available.wait(lanes).unwrap();
+    }
+  }
+
+  pub(crate) fn close(&self) {
+    self.lanes.lock().unwrap().terminating = true;
+
+    self.available.notify_all();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::{Arc, Mutex};
+
+  fn drain(queue: &JobQueue) {
+    queue.close();
+
+    while let Message::NewJob(job) = queue.pop() {
+      job();
+    }
+  }
+
+  fn push(
+    queue: &JobQueue,
+    order: &Arc<Mutex<Vec<&'static str>>>,
+    priority: Priority,
+    name: &'static str,
+  ) {
+    let order = Arc::clone(order);
+    queue.push(priority, Box::new(move || order.lock().unwrap().push(name)));
+  }
+
+  #[test]
+  fn high_priority_jobs_are_dequeued_first() {
+    let queue = JobQueue::new();
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    push(&queue, &order, Priority::Low, "report");
+    push(&queue, &order, Priority::High, "health-check");
+
+    drain(&queue);
+
+    assert_eq!(vec!["health-check", "report"], *order.lock().unwrap());
+  }
+
+  #[test]
+  fn low_priority_jobs_make_progress() {
+    let queue = JobQueue::new();
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    for _ in 0..10 {
+      push(&queue, &order, Priority::High, "high");
+    }
+    push(&queue, &order, Priority::Low, "low");
+
+    drain(&queue);
+
+    // The low lane gets its turn after the high lane spends its weight.
+    let position = order.lock().unwrap().iter().position(|&name| name == "low");
+    assert_eq!(Some(Priority::High.weight()), position);
+  }
+}
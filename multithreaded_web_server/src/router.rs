@@ -0,0 +1,317 @@
+// Sends each request to the handler registered for its method and path.
+//
+// Paths are matched segment by segment, and a segment written as
+// {name} matches anything, which the handler gets back with
+// Request::param:
+//
+//   router.add(Method::Get, "/posts/{id}", |request| {
+//     Response::ok(format!("post {}", request.param("id").unwrap()))
+//   });
+//
+// The attribute_like_macros crate generates these calls from
+// #[route(GET, "/posts/{id}")] functions.
+use std::{collections::HashMap, fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+  Get,
+  Post,
+  Put,
+  Patch,
+  Delete,
+  Head,
+}
+
+impl FromStr for Method {
+  type Err = ();
+
+  fn from_str(method: &str) -> Result<Self, Self::Err> {
+    match method {
+      "GET" => Ok(Method::Get),
+      "POST" => Ok(Method::Post),
+      "PUT" => Ok(Method::Put),
+      "PATCH" => Ok(Method::Patch),
+      "DELETE" => Ok(Method::Delete),
+      "HEAD" => Ok(Method::Head),
+      _ => Err(()),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+  pub method: Method,
+  pub path: String,
+  /// Filled in by the router from the {name} segments of the route.
+  params: HashMap<String, String>,
+}
+
+impl Request {
+  pub fn new(method: Method, path: &str) -> Self {
+    Self {
+      method,
+      path: path.to_string(),
+      params: HashMap::new(),
+    }
+  }
+
+  /// Reads the request line, as in `GET /posts/1 HTTP/1.1`.
+  ///
+  /// Headers and body are ignored. The query string, if any,
+  /// isn't part of the path.
+  pub fn parse(request: &[u8]) -> Option<Self> {
+    let request = std::str::from_utf8(request).ok()?;
+    let mut parts = request.lines().next()?.split_whitespace();
+
+    let method = parts.next()?.parse().ok()?;
+    let target = parts.next()?;
+    let path = target.split('?').next().unwrap_or(target);
+
+    Some(Self::new(method, path))
+  }
+
+  pub fn param(&self, name: &str) -> Option<&str> {
+    self.params.get(name).map(String::as_str)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+  pub status: u16,
+  pub body: String,
+}
+
+impl Response {
+  pub fn new(status: u16, body: impl Into<String>) -> Self {
+    Self {
+      status,
+      body: body.into(),
+    }
+  }
+
+  pub fn ok(body: impl Into<String>) -> Self {
+    Self::new(200, body)
+  }
+
+  pub fn bad_request(body: impl Into<String>) -> Self {
+    Self::new(400, body)
+  }
+
+  pub fn not_found(body: impl Into<String>) -> Self {
+    Self::new(404, body)
+  }
+
+  fn reason(&self) -> &'static str {
+    match self.status {
+      200 => "OK",
+      400 => "BAD REQUEST",
+      404 => "NOT FOUND",
+      405 => "METHOD NOT ALLOWED",
+      500 => "INTERNAL SERVER ERROR",
+      _ => "",
+    }
+  }
+
+  /// The response as it is written to the connection.
+  pub fn to_http(&self) -> String {
+    format!(
+      "HTTP/1.1 {} {}\r\nContent-Length: {}\r\n\r\n{}",
+      self.status,
+      self.reason(),
+      self.body.len(),
+      self.body
+    )
+  }
+}
+
+// Handlers can return a body and let it become a 200 response.
+impl From<String> for Response {
+  fn from(body: String) -> Self {
+    Response::ok(body)
+  }
+}
+
+impl From<&str> for Response {
+  fn from(body: &str) -> Self {
+    Response::ok(body)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+  Literal(String),
+  Param(String),
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+  path.split('/').filter(|segment| !segment.is_empty())
+}
+
+type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+
+struct Route {
+  method: Method,
+  path: String,
+  segments: Vec<Segment>,
+  handler: Handler,
+}
+
+impl Route {
+  /// The params of `path` if it matches the route.
+  fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    let mut path = segments(path);
+
+    for segment in &self.segments {
+      let part = path.next()?;
+
+      match segment {
+        Segment::Literal(literal) if literal != part => return None,
+        Segment::Literal(_) => {}
+        Segment::Param(name) => {
+          params.insert(name.clone(), part.to_string());
+        }
+      }
+    }
+
+    if path.next().is_some() {
+      return None;
+    }
+
+    Some(params)
+  }
+}
+
+pub struct Router {
+  routes: Vec<Route>,
+  not_found: Handler,
+}
+
+impl fmt::Debug for Router {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list()
+      .entries(
+        self
+          .routes
+          .iter()
+          .map(|route| format!("{:?} {}", route.method, route.path)),
+      )
+      .finish()
+  }
+}
+
+impl Default for Router {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Router {
+  pub fn new() -> Self {
+    Self {
+      routes: Vec::new(),
+      not_found: Box::new(|_| Response::not_found("Not Found")),
+    }
+  }
+
+  /// Sends `method` requests for paths matching `path` to `handler`.
+  ///
+  /// Routes are tried in the order they were added.
+  pub fn add(
+    &mut self,
+    method: Method,
+    path: &str,
+    handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+  ) {
+    let segments = segments(path)
+      .map(
+        |segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+          Some(name) => Segment::Param(name.to_string()),
+          None => Segment::Literal(segment.to_string()),
+        },
+      )
+      .collect();
+
+    self.routes.push(Route {
+      method,
+      path: path.to_string(),
+      segments,
+      handler: Box::new(handler),
+    });
+  }
+
+  /// Handles requests no route matches.
+  pub fn not_found(&mut self, handler: impl Fn(&Request) -> Response + Send + Sync + 'static) {
+    self.not_found = Box::new(handler);
+  }
+
+  pub fn handle(&self, mut request: Request) -> Response {
+    let mut path_matched = false;
+
+    for route in &self.routes {
+      if let Some(params) = route.matches(&request.path) {
+        if route.method != request.method {
+          path_matched = true;
+          continue;
+        }
+
+        request.params = params;
+        return (route.handler)(&request);
+      }
+    }
+
+    // The path exists, just not for this method.
+    if path_matched {
+      return Response::new(405, "Method Not Allowed");
+    }
+
+    (self.not_found)(&request)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn router() -> Router {
+    let mut router = Router::new();
+    router.add(Method::Get, "/", |_| Response::ok("index"));
+    router.add(Method::Get, "/posts/{id}/comments/{comment}", |request| {
+      Response::ok(format!(
+        "{} of {}",
+        request.param("comment").unwrap(),
+        request.param("id").unwrap()
+      ))
+    });
+    router
+  }
+
+  #[test]
+  fn routes_by_method_and_path() {
+    let router = router();
+
+    assert_eq!(
+      Response::ok("index"),
+      router.handle(Request::parse(b"GET / HTTP/1.1\r\n").unwrap())
+    );
+    assert_eq!(
+      Response::ok("7 of 3"),
+      router.handle(Request::new(Method::Get, "/posts/3/comments/7/"))
+    );
+    assert_eq!(405, router.handle(Request::new(Method::Post, "/")).status);
+    assert_eq!(
+      404,
+      router
+        .handle(Request::new(Method::Get, "/posts/3/comments"))
+        .status
+    );
+  }
+
+  #[test]
+  fn parses_request_lines() {
+    let request = Request::parse(b"DELETE /posts/1?force=yes HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+
+    assert_eq!(Request::new(Method::Delete, "/posts/1"), request);
+    assert_eq!(None, Request::parse(b"BREW /pot HTCPCP/1.0\r\n"));
+  }
+}
@@ -0,0 +1,395 @@
+use std::{
+  cmp::Ordering,
+  collections::BinaryHeap,
+  sync::{
+    atomic::{AtomicBool, Ordering as AtomicOrdering},
+    Arc, Condvar, Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{queue::JobQueue, Priority};
+
+/// Where the scheduler gets the current time from.
+///
+/// Time is measured as the [Duration] elapsed since the clock
+/// was created, which lets tests drive it by hand with [VirtualClock].
+pub trait Clock: Send + Sync {
+  fn now(&self) -> Duration;
+
+  /// How long the timer thread should block waiting for `deadline`.
+  ///
+  /// `None` means the thread should block until it is woken up.
+  fn wait_for(&self, deadline: Duration) -> Option<Duration> {
+    Some(deadline.saturating_sub(self.now()))
+  }
+
+  /// Registers a callback to run whenever time moves by means other
+  /// than waiting, which is how a [VirtualClock] fires due timers.
+  fn on_advance(&self, _callback: Box<dyn Fn() + Send + Sync>) {}
+}
+
+/// The wall clock, used by [crate::ThreadPool::new].
+pub struct SystemClock {
+  start: Instant,
+}
+
+impl SystemClock {
+  pub fn new() -> Self {
+    Self {
+      start: Instant::now(),
+    }
+  }
+}
+
+impl Default for SystemClock {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Clock for SystemClock {
+  fn now(&self) -> Duration {
+    self.start.elapsed()
+  }
+}
+
+/// A clock that only moves when [VirtualClock::advance] is called.
+///
+/// Due jobs are handed to the pool before `advance` returns,
+/// so tests don't have to sleep.
+#[derive(Default)]
+pub struct VirtualClock {
+  now: Mutex<Duration>,
+  callbacks: Mutex<Vec<Box<dyn Fn() + Send + Sync>>>,
+}
+
+impl VirtualClock {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn advance(&self, duration: Duration) {
+    *self.now.lock().unwrap() += duration;
+
+    for callback in self.callbacks.lock().unwrap().iter() {
+      callback();
+    }
+  }
+}
+
+impl Clock for VirtualClock {
+  fn now(&self) -> Duration {
+    *self.now.lock().unwrap()
+  }
+
+  fn wait_for(&self, _deadline: Duration) -> Option<Duration> {
+    // Waiting doesn't move virtual time, only advance does.
+    None
+  }
+
+  fn on_advance(&self, callback: Box<dyn Fn() + Send + Sync>) {
+    self.callbacks.lock().unwrap().push(callback);
+  }
+}
+
+/// Returned by [crate::ThreadPool::schedule_after] and
+/// [crate::ThreadPool::schedule_every].
+#[derive(Clone)]
+pub struct ScheduleHandle {
+  cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduleHandle {
+  /// Stops the job from running again.
+  ///
+  /// A run that was already handed to the pool is not interrupted.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, AtomicOrdering::SeqCst);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(AtomicOrdering::SeqCst)
+  }
+}
+
+enum Task {
+  Once(Box<dyn FnOnce() + Send + 'static>),
+  // Periodic jobs run many times, so they can't be FnOnce.
+  Every(Duration, Arc<dyn Fn() + Send + Sync + 'static>),
+}
+
+struct Timer {
+  deadline: Duration,
+  // Breaks ties between timers with the same deadline
+  // so they fire in the order they were scheduled.
+  seq: u64,
+  cancelled: Arc<AtomicBool>,
+  task: Task,
+}
+
+// BinaryHeap is a max-heap, the orderings are reversed
+// so the earliest deadline is at the top.
+impl Ord for Timer {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .deadline
+      .cmp(&self.deadline)
+      .then_with(|| other.seq.cmp(&self.seq))
+  }
+}
+
+impl PartialOrd for Timer {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for Timer {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Timer {}
+
+struct Timers {
+  heap: BinaryHeap<Timer>,
+  next_seq: u64,
+  shutdown: bool,
+}
+
+impl Timers {
+  /// Hands every timer whose deadline has passed to the pool,
+  /// rescheduling the periodic ones.
+  fn dispatch_due(&mut self, now: Duration, queue: &JobQueue) {
+    while self.heap.peek().is_some_and(|timer| timer.deadline <= now) {
+      let timer = self.heap.pop().unwrap();
+
+      if timer.cancelled.load(AtomicOrdering::SeqCst) {
+        continue;
+      }
+
+      match timer.task {
+        Task::Once(job) => queue.push(Priority::Normal, job),
+        Task::Every(interval, job) => {
+          let run = Arc::clone(&job);
+          queue.push(Priority::Normal, Box::new(move || run()));
+
+          let seq = self.next_seq;
+          self.next_seq += 1;
+
+          self.heap.push(Timer {
+            deadline: timer.deadline + interval,
+            seq,
+            cancelled: timer.cancelled,
+            task: Task::Every(interval, job),
+          });
+        }
+      }
+    }
+  }
+}
+
+struct Shared {
+  timers: Mutex<Timers>,
+  changed: Condvar,
+}
+
+/// Keeps pending timers in a heap ordered by deadline and
+/// moves them to the job queue once they are due.
+pub(crate) struct Scheduler {
+  shared: Arc<Shared>,
+  clock: Arc<dyn Clock>,
+  thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Scheduler {
+  pub(crate) fn new(clock: Arc<dyn Clock>, queue: Arc<JobQueue>) -> Self {
+    let shared = Arc::new(Shared {
+      timers: Mutex::new(Timers {
+        heap: BinaryHeap::new(),
+        next_seq: 0,
+        shutdown: false,
+      }),
+      changed: Condvar::new(),
+    });
+
+    {
+      // Weak so the clock doesn't keep the scheduler alive.
+      let shared = Arc::downgrade(&shared);
+      let queue = Arc::downgrade(&queue);
+      let callback_clock = Arc::downgrade(&clock);
+
+      clock.on_advance(Box::new(move || {
+        if let (Some(shared), Some(queue), Some(clock)) =
+          (shared.upgrade(), queue.upgrade(), callback_clock.upgrade())
+        {
+          let now = clock.now();
+          shared.timers.lock().unwrap().dispatch_due(now, &queue);
+        }
+      }));
+    }
+
+    let thread = {
+      let shared = Arc::clone(&shared);
+      let clock = Arc::clone(&clock);
+
+      thread::spawn(move || {
+        let mut timers = shared.timers.lock().unwrap();
+
+        while !timers.shutdown {
+          timers.dispatch_due(clock.now(), &queue);
+
+          let timeout = timers
+            .heap
+            .peek()
+            .and_then(|timer| clock.wait_for(timer.deadline));
+
+          timers = match timeout {
+            Some(timeout) => shared.changed.wait_timeout(timers, timeout).unwrap().0,
+            None => shared.changed.wait(timers).unwrap(),
+          };
+        }
+      })
+    };
+
+    Self {
+      shared,
+      clock,
+      thread: Some(thread),
+    }
+  }
+
+  fn schedule(&self, delay: Duration, task: Task) -> ScheduleHandle {
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let mut timers = self.shared.timers.lock().unwrap();
+
+    let seq = timers.next_seq;
+    timers.next_seq += 1;
+
+    timers.heap.push(Timer {
+      deadline: self.clock.now() + delay,
+      seq,
+      cancelled: Arc::clone(&cancelled),
+      task,
+    });
+
+    // The new timer may be due before the one the thread is waiting for.
+    self.shared.changed.notify_one();
+
+    ScheduleHandle { cancelled }
+  }
+
+  pub(crate) fn once(
+    &self,
+    delay: Duration,
+    job: impl FnOnce() + Send + 'static,
+  ) -> ScheduleHandle {
+    self.schedule(delay, Task::Once(Box::new(job)))
+  }
+
+  pub(crate) fn every(
+    &self,
+    interval: Duration,
+    job: impl Fn() + Send + Sync + 'static,
+  ) -> ScheduleHandle {
+    self.schedule(interval, Task::Every(interval, Arc::new(job)))
+  }
+}
+
+impl Drop for Scheduler {
+  fn drop(&mut self) {
+    // Timers that are not due yet are discarded.
+    self.shared.timers.lock().unwrap().shutdown = true;
+    self.shared.changed.notify_one();
+
+    if let Some(thread) = self.thread.take() {
+      thread.join().unwrap();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::ThreadPool;
+
+  use super::*;
+  use std::sync::mpsc;
+
+  fn pool() -> (ThreadPool, Arc<VirtualClock>) {
+    let clock = Arc::new(VirtualClock::new());
+    let pool = ThreadPool::with_clock(2, Arc::clone(&clock) as Arc<dyn Clock>);
+
+    (pool, clock)
+  }
+
+  #[test]
+  fn schedule_after_runs_once_the_delay_has_passed() {
+    let (pool, clock) = pool();
+    let (sender, receiver) = mpsc::channel();
+
+    let later = sender.clone();
+    pool.schedule_after(Duration::from_secs(10), move || {
+      later.send("later").unwrap();
+    });
+    pool.schedule_after(Duration::from_secs(5), move || {
+      sender.send("sooner").unwrap();
+    });
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!("sooner", receiver.recv().unwrap());
+    assert!(receiver.try_recv().is_err());
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!("later", receiver.recv().unwrap());
+  }
+
+  #[test]
+  fn schedule_every_repeats_until_cancelled() {
+    let (pool, clock) = pool();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+
+    let handle = pool.schedule_every(Duration::from_secs(1), move || {
+      sender.lock().unwrap().send(()).unwrap();
+    });
+
+    for _ in 0..3 {
+      clock.advance(Duration::from_secs(1));
+      receiver.recv().unwrap();
+    }
+
+    handle.cancel();
+    clock.advance(Duration::from_secs(1));
+
+    drop(pool);
+    assert_eq!(0, receiver.try_iter().count());
+  }
+
+  #[test]
+  #[should_panic(expected = "the interval must not be zero")]
+  fn schedule_every_rejects_a_zero_interval() {
+    let (pool, _clock) = pool();
+
+    pool.schedule_every(Duration::ZERO, || {});
+  }
+
+  #[test]
+  fn cancelled_jobs_never_run() {
+    let (pool, clock) = pool();
+    let (sender, receiver) = mpsc::channel();
+
+    let handle = pool.schedule_after(Duration::from_secs(1), move || {
+      sender.send(()).unwrap();
+    });
+    handle.cancel();
+
+    clock.advance(Duration::from_secs(1));
+
+    drop(pool);
+    assert!(receiver.try_recv().is_err());
+  }
+}
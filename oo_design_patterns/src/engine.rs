@@ -0,0 +1,635 @@
+// A state machine defined at runtime.
+//
+// The State trait bakes the workflow into the code: every state is a
+// type and every transition a method. That's not an option when the
+// workflow comes from a configuration file, so here the states and
+// transitions are plain data:
+//
+//   {
+//     "initial": "draft",
+//     "states": [
+//       { "name": "draft" },
+//       { "name": "review", "on_entry": ["notify"] },
+//       { "name": "published", "terminal": true }
+//     ],
+//     "transitions": [
+//       { "from": "draft", "event": "submit", "to": "review" },
+//       { "from": "review", "event": "approve", "to": "published", "guard": "has_title" }
+//     ]
+//   }
+//
+// Guards and actions are referred to by name, and the code running the
+// workflow gives each name a closure when it builds the Engine.
+// Building checks the workflow first, so a workflow with unreachable
+// states or no way to ever finish is caught before anything runs.
+use std::{collections::HashSet, error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateDef {
+  pub name: String,
+  /// Terminal states are where the workflow ends, they have no transitions out.
+  #[serde(default)]
+  pub terminal: bool,
+  /// Actions run when the machine enters the state.
+  #[serde(default)]
+  pub on_entry: Vec<String>,
+  /// Actions run when the machine leaves the state.
+  #[serde(default)]
+  pub on_exit: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransitionDef {
+  pub from: String,
+  pub event: String,
+  pub to: String,
+  /// The transition only happens if this guard holds.
+  #[serde(default)]
+  pub guard: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workflow {
+  pub initial: String,
+  pub states: Vec<StateDef>,
+  pub transitions: Vec<TransitionDef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+  DuplicateState(String),
+  /// A transition, or the initial state, names a state that isn't defined.
+  UnknownState(String),
+  /// `event` leads to more than one state from `from`.
+  DuplicateTransition {
+    from: String,
+    event: String,
+  },
+  /// There is no way to get to the state from the initial one.
+  Unreachable(String),
+  /// The workflow can never finish.
+  NoTerminalState,
+  /// A state that isn't terminal but can't be left either.
+  DeadEnd(String),
+  /// A terminal state with transitions out of it.
+  TerminalWithTransitions(String),
+  /// A state that can be reached and left, but from which no
+  /// terminal state can be reached, as in a -> b -> a.
+  CannotFinish(String),
+  UnknownGuard(String),
+  UnknownAction(String),
+}
+
+impl fmt::Display for ValidationError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValidationError::DuplicateState(state) => write!(f, "{} is defined more than once", state),
+      ValidationError::UnknownState(state) => write!(f, "there is no state {}", state),
+      ValidationError::DuplicateTransition { from, event } => {
+        write!(f, "{} has more than one transition for {}", from, event)
+      }
+      ValidationError::Unreachable(state) => write!(f, "{} can't be reached", state),
+      ValidationError::NoTerminalState => write!(f, "there is no terminal state"),
+      ValidationError::DeadEnd(state) => {
+        write!(f, "{} has no transitions out but isn't terminal", state)
+      }
+      ValidationError::TerminalWithTransitions(state) => {
+        write!(f, "{} is terminal but has transitions out", state)
+      }
+      ValidationError::CannotFinish(state) => {
+        write!(f, "no terminal state can be reached from {}", state)
+      }
+      ValidationError::UnknownGuard(guard) => write!(f, "there is no guard {}", guard),
+      ValidationError::UnknownAction(action) => write!(f, "there is no action {}", action),
+    }
+  }
+}
+
+impl Error for ValidationError {}
+
+impl Workflow {
+  fn state(&self, name: &str) -> Option<&StateDef> {
+    self.states.iter().find(|state| state.name == name)
+  }
+
+  fn transitions_from<'a>(&'a self, state: &'a str) -> impl Iterator<Item = &'a TransitionDef> {
+    self
+      .transitions
+      .iter()
+      .filter(move |transition| transition.from == state)
+  }
+
+  /// Everything wrong with the workflow, empty if nothing is.
+  ///
+  /// Guards and actions aren't checked here since they only get
+  /// their closures when the engine is built.
+  pub fn validate(&self) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+
+    let mut names = HashSet::new();
+    for state in &self.states {
+      if !names.insert(state.name.as_str()) {
+        errors.push(ValidationError::DuplicateState(state.name.clone()));
+      }
+    }
+
+    let mut unknown = HashSet::new();
+    let mentioned = std::iter::once(&self.initial).chain(
+      self
+        .transitions
+        .iter()
+        .flat_map(|transition| vec![&transition.from, &transition.to]),
+    );
+    for name in mentioned {
+      if !names.contains(name.as_str()) && unknown.insert(name) {
+        errors.push(ValidationError::UnknownState(name.clone()));
+      }
+    }
+
+    let mut seen = HashSet::new();
+    for transition in &self.transitions {
+      if !seen.insert((&transition.from, &transition.event)) {
+        errors.push(ValidationError::DuplicateTransition {
+          from: transition.from.clone(),
+          event: transition.event.clone(),
+        });
+      }
+    }
+
+    // Walks the transitions from the initial state, whatever
+    // isn't visited can't be reached.
+    let mut reachable = HashSet::new();
+    let mut stack = vec![self.initial.as_str()];
+    while let Some(state) = stack.pop() {
+      if reachable.insert(state) {
+        stack.extend(self.transitions_from(state).map(|t| t.to.as_str()));
+      }
+    }
+
+    // The same walk backwards from the terminal states, whatever
+    // isn't visited can't get to one of them.
+    let terminal: Vec<&str> = self
+      .states
+      .iter()
+      .filter(|state| state.terminal)
+      .map(|state| state.name.as_str())
+      .collect();
+
+    let mut can_finish = HashSet::new();
+    let mut stack = terminal.clone();
+    while let Some(state) = stack.pop() {
+      if can_finish.insert(state) {
+        stack.extend(
+          self
+            .transitions
+            .iter()
+            .filter(|t| t.to == state)
+            .map(|t| t.from.as_str()),
+        );
+      }
+    }
+
+    for state in &self.states {
+      let is_reachable = reachable.contains(state.name.as_str());
+      if !is_reachable {
+        errors.push(ValidationError::Unreachable(state.name.clone()));
+      }
+
+      let has_transitions = self.transitions_from(&state.name).next().is_some();
+      match (state.terminal, has_transitions) {
+        (true, true) => errors.push(ValidationError::TerminalWithTransitions(state.name.clone())),
+        (false, false) => errors.push(ValidationError::DeadEnd(state.name.clone())),
+        // Dead ends, and every state when there is no terminal
+        // one, are already reported.
+        (false, true)
+          if is_reachable && !terminal.is_empty() && !can_finish.contains(state.name.as_str()) =>
+        {
+          errors.push(ValidationError::CannotFinish(state.name.clone()))
+        }
+        _ => {}
+      }
+    }
+
+    if terminal.is_empty() {
+      errors.push(ValidationError::NoTerminalState);
+    }
+
+    errors
+  }
+
+  /// The workflow as a Graphviz graph, `dot -Tsvg` turns it into a picture.
+  ///
+  /// The initial state is drawn in bold, terminal states with a double
+  /// border and guards in brackets after the event.
+  pub fn to_dot(&self) -> String {
+    let mut dot = String::from("digraph workflow {\n  rankdir=LR;\n");
+
+    for state in &self.states {
+      let mut attributes = Vec::new();
+      if state.terminal {
+        attributes.push("shape=doublecircle");
+      } else {
+        attributes.push("shape=circle");
+      }
+      if state.name == self.initial {
+        attributes.push("style=bold");
+      }
+
+      dot.push_str(&format!(
+        "  {} [{}];\n",
+        quoted(&state.name),
+        attributes.join(", ")
+      ));
+    }
+
+    for transition in &self.transitions {
+      let label = match &transition.guard {
+        Some(guard) => format!("{} [{}]", transition.event, guard),
+        None => transition.event.clone(),
+      };
+
+      dot.push_str(&format!(
+        "  {} -> {} [label={}];\n",
+        quoted(&transition.from),
+        quoted(&transition.to),
+        quoted(&label)
+      ));
+    }
+
+    dot.push_str("}\n");
+    dot
+  }
+}
+
+/// A DOT string literal.
+fn quoted(text: &str) -> String {
+  format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+  /// `state` has no transition for `event`.
+  NoTransition { state: String, event: String },
+  /// The transition exists but `guard` didn't hold.
+  GuardFailed {
+    state: String,
+    event: String,
+    guard: String,
+  },
+}
+
+impl fmt::Display for EngineError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      EngineError::NoTransition { state, event } => {
+        write!(f, "{} has no transition for {}", state, event)
+      }
+      EngineError::GuardFailed {
+        state,
+        event,
+        guard,
+      } => write!(f, "can't {} from {}: {} doesn't hold", event, state, guard),
+    }
+  }
+}
+
+impl Error for EngineError {}
+
+type GuardFn<C> = Box<dyn Fn(&C) -> bool>;
+
+type ActionFn<C> = Box<dyn Fn(&mut C)>;
+
+/// Gives the guards and actions of a workflow their closures.
+///
+/// `C` is the context: whatever the guards look at and the actions change.
+pub struct EngineBuilder<C> {
+  workflow: Workflow,
+  guards: Vec<(String, GuardFn<C>)>,
+  actions: Vec<(String, ActionFn<C>)>,
+}
+
+impl<C> EngineBuilder<C> {
+  pub fn guard(mut self, name: &str, guard: impl Fn(&C) -> bool + 'static) -> Self {
+    self.guards.push((name.to_string(), Box::new(guard)));
+    self
+  }
+
+  pub fn action(mut self, name: &str, action: impl Fn(&mut C) + 'static) -> Self {
+    self.actions.push((name.to_string(), Box::new(action)));
+    self
+  }
+
+  /// Validates the workflow, and checks that every guard and action
+  /// it refers to was given a closure.
+  pub fn build(self) -> Result<Engine<C>, Vec<ValidationError>> {
+    let mut errors = self.workflow.validate();
+
+    let mut missing_guards = HashSet::new();
+    for guard in self
+      .workflow
+      .transitions
+      .iter()
+      .filter_map(|t| t.guard.as_ref())
+    {
+      if !self.guards.iter().any(|(name, _)| name == guard) && missing_guards.insert(guard) {
+        errors.push(ValidationError::UnknownGuard(guard.clone()));
+      }
+    }
+
+    let mut missing_actions = HashSet::new();
+    let actions = self
+      .workflow
+      .states
+      .iter()
+      .flat_map(|state| state.on_entry.iter().chain(&state.on_exit));
+    for action in actions {
+      if !self.actions.iter().any(|(name, _)| name == action) && missing_actions.insert(action) {
+        errors.push(ValidationError::UnknownAction(action.clone()));
+      }
+    }
+
+    if !errors.is_empty() {
+      return Err(errors);
+    }
+
+    Ok(Engine {
+      workflow: self.workflow,
+      guards: self.guards,
+      actions: self.actions,
+    })
+  }
+}
+
+/// A validated workflow, ready to run.
+pub struct Engine<C> {
+  workflow: Workflow,
+  guards: Vec<(String, GuardFn<C>)>,
+  actions: Vec<(String, ActionFn<C>)>,
+}
+
+/// One run of a workflow: the state it is in and its context.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance<C> {
+  state: String,
+  pub context: C,
+}
+
+impl<C> Instance<C> {
+  pub fn state(&self) -> &str {
+    &self.state
+  }
+}
+
+impl<C> Engine<C> {
+  pub fn builder(workflow: Workflow) -> EngineBuilder<C> {
+    EngineBuilder {
+      workflow,
+      guards: Vec::new(),
+      actions: Vec::new(),
+    }
+  }
+
+  pub fn workflow(&self) -> &Workflow {
+    &self.workflow
+  }
+
+  fn run(&self, actions: &[String], context: &mut C) {
+    for action in actions {
+      // build() made sure every action has a closure.
+      let (_, run) = self
+        .actions
+        .iter()
+        .find(|(name, _)| name == action)
+        .unwrap();
+      run(context);
+    }
+  }
+
+  /// Starts a run in the initial state, running its entry actions.
+  pub fn start(&self, mut context: C) -> Instance<C> {
+    let initial = self.workflow.state(&self.workflow.initial).unwrap();
+    self.run(&initial.on_entry, &mut context);
+
+    Instance {
+      state: initial.name.clone(),
+      context,
+    }
+  }
+
+  /// Takes the transition out of the instance's state for `event`.
+  ///
+  /// The exit actions of the old state run before the entry
+  /// actions of the new one, even when both are the same state.
+  /// Nothing runs if the transition can't be taken.
+  pub fn fire(&self, instance: &mut Instance<C>, event: &str) -> Result<(), EngineError> {
+    let transition = self
+      .workflow
+      .transitions_from(&instance.state)
+      .find(|transition| transition.event == event)
+      .ok_or_else(|| EngineError::NoTransition {
+        state: instance.state.clone(),
+        event: event.to_string(),
+      })?;
+
+    if let Some(guard) = &transition.guard {
+      let (_, holds) = self.guards.iter().find(|(name, _)| name == guard).unwrap();
+
+      if !holds(&instance.context) {
+        return Err(EngineError::GuardFailed {
+          state: instance.state.clone(),
+          event: event.to_string(),
+          guard: guard.clone(),
+        });
+      }
+    }
+
+    let from = self.workflow.state(&transition.from).unwrap();
+    let to = self.workflow.state(&transition.to).unwrap();
+
+    self.run(&from.on_exit, &mut instance.context);
+    instance.state = to.name.clone();
+    self.run(&to.on_entry, &mut instance.context);
+
+    Ok(())
+  }
+
+  pub fn is_finished(&self, instance: &Instance<C>) -> bool {
+    self
+      .workflow
+      .state(&instance.state)
+      .is_some_and(|state| state.terminal)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The blog post workflow of lib.rs, as data.
+  const POSTS: &str = r#"{
+    "initial": "draft",
+    "states": [
+      { "name": "draft", "on_exit": ["count_revision"] },
+      { "name": "pending review", "on_entry": ["notify"] },
+      { "name": "published", "terminal": true, "on_entry": ["notify"] }
+    ],
+    "transitions": [
+      { "from": "draft", "event": "request_review", "to": "pending review" },
+      { "from": "pending review", "event": "approve", "to": "published", "guard": "has_content" },
+      { "from": "pending review", "event": "reject", "to": "draft" }
+    ]
+  }"#;
+
+  #[derive(Default)]
+  struct Post {
+    content: String,
+    revisions: usize,
+    notifications: Vec<&'static str>,
+  }
+
+  fn engine() -> Engine<Post> {
+    Engine::builder(serde_json::from_str(POSTS).unwrap())
+      .guard("has_content", |post: &Post| !post.content.is_empty())
+      .action("count_revision", |post: &mut Post| post.revisions += 1)
+      .action("notify", |post: &mut Post| {
+        post.notifications.push("reviewers")
+      })
+      .build()
+      .unwrap()
+  }
+
+  #[test]
+  fn runs_guards_and_actions() {
+    let engine = engine();
+    let mut post = engine.start(Post::default());
+
+    assert_eq!(
+      Err(EngineError::NoTransition {
+        state: "draft".to_string(),
+        event: "approve".to_string()
+      }),
+      engine.fire(&mut post, "approve")
+    );
+
+    engine.fire(&mut post, "request_review").unwrap();
+    assert_eq!(
+      Err(EngineError::GuardFailed {
+        state: "pending review".to_string(),
+        event: "approve".to_string(),
+        guard: "has_content".to_string()
+      }),
+      engine.fire(&mut post, "approve")
+    );
+
+    engine.fire(&mut post, "reject").unwrap();
+    post.context.content.push_str("salad");
+    engine.fire(&mut post, "request_review").unwrap();
+    engine.fire(&mut post, "approve").unwrap();
+
+    assert_eq!("published", post.state());
+    assert!(engine.is_finished(&post));
+    assert_eq!(2, post.context.revisions);
+    assert_eq!(3, post.context.notifications.len());
+  }
+
+  #[test]
+  fn finds_broken_workflows() {
+    let mut workflow: Workflow = serde_json::from_str(POSTS).unwrap();
+    workflow.states[2].terminal = false;
+    workflow.states.push(StateDef {
+      name: "archived".to_string(),
+      terminal: true,
+      on_entry: Vec::new(),
+      on_exit: Vec::new(),
+    });
+    workflow.transitions.push(TransitionDef {
+      from: "draft".to_string(),
+      event: "delete".to_string(),
+      to: "deleted".to_string(),
+      guard: None,
+    });
+
+    assert_eq!(
+      vec![
+        ValidationError::UnknownState("deleted".to_string()),
+        ValidationError::CannotFinish("draft".to_string()),
+        ValidationError::CannotFinish("pending review".to_string()),
+        ValidationError::DeadEnd("published".to_string()),
+        ValidationError::Unreachable("archived".to_string()),
+      ],
+      workflow.validate()
+    );
+
+    workflow.states.pop();
+    workflow.transitions.pop();
+    assert_eq!(
+      vec![
+        ValidationError::DeadEnd("published".to_string()),
+        ValidationError::NoTerminalState,
+      ],
+      workflow.validate()
+    );
+  }
+
+  #[test]
+  fn finds_cycles_with_no_way_out() {
+    let workflow: Workflow = serde_json::from_str(
+      r#"{
+        "initial": "a",
+        "states": [{"name": "a"}, {"name": "b"}, {"name": "done", "terminal": true}],
+        "transitions": [
+          {"from": "a", "event": "next", "to": "b"},
+          {"from": "b", "event": "back", "to": "a"}
+        ]
+      }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+      vec![
+        ValidationError::CannotFinish("a".to_string()),
+        ValidationError::CannotFinish("b".to_string()),
+        ValidationError::Unreachable("done".to_string()),
+      ],
+      workflow.validate()
+    );
+  }
+
+  #[test]
+  fn needs_every_guard_and_action() {
+    let errors = Engine::<Post>::builder(serde_json::from_str(POSTS).unwrap())
+      .action("notify", |_| {})
+      .build()
+      .err()
+      .unwrap();
+
+    assert_eq!(
+      vec![
+        ValidationError::UnknownGuard("has_content".to_string()),
+        ValidationError::UnknownAction("count_revision".to_string()),
+      ],
+      errors
+    );
+  }
+
+  #[test]
+  fn exports_dot() {
+    let workflow: Workflow = serde_json::from_str(POSTS).unwrap();
+
+    assert_eq!(
+      r#"digraph workflow {
+  rankdir=LR;
+  "draft" [shape=circle, style=bold];
+  "pending review" [shape=circle];
+  "published" [shape=doublecircle];
+  "draft" -> "pending review" [label="request_review"];
+  "pending review" -> "published" [label="approve [has_content]"];
+  "pending review" -> "draft" [label="reject"];
+}
+"#,
+      workflow.to_dot()
+    );
+  }
+}
@@ -0,0 +1,73 @@
+// Every transition a post goes through is recorded as an event.
+//
+// The history is append-only: events are only ever pushed by the
+// transitions themselves, callers can read them but not change them.
+// Since the events also say which action was taken, the history
+// can be replayed on a new draft to get back to the current state.
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{State, StateKind, Transition};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+  RequestReview,
+  Approve,
+  Reject,
+}
+
+impl Action {
+  pub(crate) fn apply(self, state: Box<dyn State>, actor: &str) -> Transition {
+    match self {
+      Action::RequestReview => state.request_review(),
+      Action::Approve => state.approve(actor),
+      Action::Reject => state.reject(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+  pub action: Action,
+  /// Who took the action.
+  pub actor: String,
+  pub timestamp: SystemTime,
+  pub from: StateKind,
+  pub to: StateKind,
+  pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct History {
+  pub(crate) events: Vec<Event>,
+}
+
+impl History {
+  pub(crate) fn push(&mut self, event: Event) {
+    self.events.push(event);
+  }
+
+  /// Every event, oldest first.
+  pub fn events(&self) -> &[Event] {
+    &self.events
+  }
+
+  pub fn by_actor<'a>(&'a self, actor: &'a str) -> impl Iterator<Item = &'a Event> + 'a {
+    self.events.iter().filter(move |event| event.actor == actor)
+  }
+
+  /// The events that moved the post into `kind`, not counting
+  /// the ones that left it where it already was.
+  pub fn entering(&self, kind: StateKind) -> impl Iterator<Item = &Event> + '_ {
+    self
+      .events
+      .iter()
+      .filter(move |event| event.to == kind && event.from != kind)
+  }
+
+  pub fn last(&self) -> Option<&Event> {
+    self.events.last()
+  }
+}
@@ -0,0 +1,575 @@
+// Implementing an object-oriented design pattern
+//
+// The state pattern is an object-oriented design pattern.
+// The crux of the pattern if that a value has some internal state,
+// which is represented by a set of state objects, and the value's
+// behaviour changes based on the internal state.
+// The state objects share functionality.
+//
+// NOTE: can we replace the state pattern with type state?
+//
+// When the workflow is only known at runtime, engine.rs runs
+// workflows described as data instead.
+use std::{error::Error, fmt, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+mod engine;
+mod history;
+mod store;
+
+pub use engine::{
+  Engine, EngineBuilder, EngineError, Instance, StateDef, TransitionDef, ValidationError, Workflow,
+};
+pub use history::{Action, Event, History};
+pub use store::{PostId, Repository, StoreError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostError {
+  /// `action` makes no sense while the post is in `state`.
+  IllegalTransition {
+    state: &'static str,
+    action: &'static str,
+  },
+  /// Text can only be added to drafts.
+  NotEditable { state: &'static str },
+  /// Every approval has to come from a different approver.
+  AlreadyApproved { approver: String },
+  /// Replaying the history didn't lead to the state the event at
+  /// `index` recorded, the history was changed by hand.
+  InconsistentHistory { index: usize },
+  /// Replaying the whole history leads to `replayed` instead of
+  /// `state`, the state the post is in. Posts saved before the
+  /// history was kept have none.
+  IncompleteHistory {
+    state: &'static str,
+    replayed: &'static str,
+  },
+}
+
+impl fmt::Display for PostError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PostError::IllegalTransition { state, action } => {
+        write!(f, "can't {} a post that is {}", action, state)
+      }
+      PostError::NotEditable { state } => write!(f, "can't edit a post that is {}", state),
+      PostError::AlreadyApproved { approver } => {
+        write!(f, "{} already approved this post", approver)
+      }
+      PostError::InconsistentHistory { index } => {
+        write!(
+          f,
+          "event {} of the history doesn't follow from the ones before it",
+          index
+        )
+      }
+      PostError::IncompleteHistory { state, replayed } => {
+        write!(
+          f,
+          "the history leads to a post that is {}, not {}",
+          replayed, state
+        )
+      }
+    }
+  }
+}
+
+impl Error for PostError {}
+
+// A state can't be left behind when a transition fails, the post
+// would end up with no state at all, so it is returned with the error.
+type Transition = Result<Box<dyn State>, (Box<dyn State>, PostError)>;
+
+fn illegal(state: Box<dyn State>, action: &'static str) -> Transition {
+  let error = PostError::IllegalTransition {
+    state: state.name(),
+    action,
+  };
+
+  Err((state, error))
+}
+
+/// Which state a post is in, without the data the state holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StateKind {
+  Draft,
+  PendingReview,
+  Published,
+}
+
+/// A state with everything it holds, as it is saved to disk.
+///
+/// Box<dyn State> can't be serialized, serde has no way to know
+/// which type is behind the trait object when reading it back.
+/// So every state is turned into a variant of this enum before
+/// being saved, and the variant tells which State to recreate.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+enum StateRecord {
+  Draft {
+    required_approvals: usize,
+  },
+  PendingReview {
+    approvers: Vec<String>,
+    required_approvals: usize,
+  },
+  Published,
+}
+
+impl StateRecord {
+  /// None for the states that don't keep track of it.
+  fn required_approvals(&self) -> Option<usize> {
+    match self {
+      StateRecord::Draft { required_approvals }
+      | StateRecord::PendingReview {
+        required_approvals, ..
+      } => Some(*required_approvals),
+      StateRecord::Published => None,
+    }
+  }
+
+  fn into_state(self) -> Box<dyn State> {
+    match self {
+      StateRecord::Draft { required_approvals } => Box::new(Draft { required_approvals }),
+      StateRecord::PendingReview {
+        approvers,
+        required_approvals,
+      } => Box::new(PendingReview {
+        approvers,
+        required_approvals,
+      }),
+      StateRecord::Published => Box::new(Published {}),
+    }
+  }
+}
+
+trait State {
+  fn name(&self) -> &'static str;
+
+  fn kind(&self) -> StateKind;
+
+  fn to_record(&self) -> StateRecord;
+
+  // We use Box<Self> here because self is a trait,
+  // and it's size is not known, but the size of a
+  // Box<T> always is known.
+  fn request_review(self: Box<Self>) -> Transition;
+
+  fn approve(self: Box<Self>, approver: &str) -> Transition;
+
+  fn reject(self: Box<Self>) -> Transition;
+
+  fn can_edit(&self) -> bool {
+    false
+  }
+
+  // The return value will live as long as [post].
+  fn content<'a>(&self, _post: &'a Post) -> &'a str {
+    ""
+  }
+}
+
+struct Draft {
+  required_approvals: usize,
+}
+
+impl State for Draft {
+  fn name(&self) -> &'static str {
+    "draft"
+  }
+
+  fn kind(&self) -> StateKind {
+    StateKind::Draft
+  }
+
+  fn to_record(&self) -> StateRecord {
+    StateRecord::Draft {
+      required_approvals: self.required_approvals,
+    }
+  }
+
+  fn request_review(self: Box<Self>) -> Transition {
+    Ok(Box::new(PendingReview {
+      approvers: Vec::new(),
+      required_approvals: self.required_approvals,
+    }))
+  }
+
+  fn approve(self: Box<Self>, _approver: &str) -> Transition {
+    illegal(self, "approve")
+  }
+
+  fn reject(self: Box<Self>) -> Transition {
+    illegal(self, "reject")
+  }
+
+  fn can_edit(&self) -> bool {
+    true
+  }
+}
+
+struct PendingReview {
+  // Who approved the post so far.
+  approvers: Vec<String>,
+  required_approvals: usize,
+}
+
+impl State for PendingReview {
+  fn name(&self) -> &'static str {
+    "pending review"
+  }
+
+  fn kind(&self) -> StateKind {
+    StateKind::PendingReview
+  }
+
+  fn to_record(&self) -> StateRecord {
+    StateRecord::PendingReview {
+      approvers: self.approvers.clone(),
+      required_approvals: self.required_approvals,
+    }
+  }
+
+  // This is an example of why this pattern is not very good
+  // if implemented in an OOP way,
+  // it clearly makes no sense for PendingReview to have a method
+  // called request_review but it has to have it because
+  // every state is implementing the same trait.
+  fn request_review(self: Box<Self>) -> Transition {
+    illegal(self, "request review of")
+  }
+
+  fn approve(mut self: Box<Self>, approver: &str) -> Transition {
+    if self.approvers.iter().any(|a| a == approver) {
+      let error = PostError::AlreadyApproved {
+        approver: approver.to_string(),
+      };
+      return Err((self, error));
+    }
+
+    self.approvers.push(approver.to_string());
+
+    if self.approvers.len() < self.required_approvals {
+      Ok(self)
+    } else {
+      Ok(Box::new(Published {}))
+    }
+  }
+
+  // Approvals given so far don't count for the next review.
+  fn reject(self: Box<Self>) -> Transition {
+    Ok(Box::new(Draft {
+      required_approvals: self.required_approvals,
+    }))
+  }
+}
+
+struct Published {}
+
+impl State for Published {
+  fn name(&self) -> &'static str {
+    "published"
+  }
+
+  fn kind(&self) -> StateKind {
+    StateKind::Published
+  }
+
+  fn to_record(&self) -> StateRecord {
+    StateRecord::Published
+  }
+
+  fn request_review(self: Box<Self>) -> Transition {
+    illegal(self, "request review of")
+  }
+
+  fn approve(self: Box<Self>, _approver: &str) -> Transition {
+    illegal(self, "approve")
+  }
+
+  fn reject(self: Box<Self>) -> Transition {
+    illegal(self, "reject")
+  }
+
+  fn content<'a>(&self, post: &'a Post) -> &'a str {
+    &post.content
+  }
+}
+
+pub struct Post {
+  state: Option<Box<dyn State>>,
+  content: String,
+  required_approvals: usize,
+  history: History,
+}
+
+impl Default for Post {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Post {
+  /// Creates a post that is published after one approval.
+  pub fn new() -> Self {
+    Self::with_required_approvals(1)
+  }
+
+  /// Creates a post that needs `required_approvals` approvals,
+  /// from different approvers, to be published.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `required_approvals` is 0.
+  pub fn with_required_approvals(required_approvals: usize) -> Self {
+    assert!(required_approvals > 0);
+
+    Self {
+      state: Some(Box::new(Draft { required_approvals })),
+      content: String::new(),
+      required_approvals,
+      history: History::default(),
+    }
+  }
+
+  fn state(&self) -> &dyn State {
+    // Calling Option::as_ref because we want
+    // a reference to the value inside the Option.
+    // &Option<T>::as_ref returns Option<&T>.
+    self.state.as_ref().unwrap().as_ref()
+  }
+
+  pub fn state_name(&self) -> &'static str {
+    self.state().name()
+  }
+
+  pub fn state_kind(&self) -> StateKind {
+    self.state().kind()
+  }
+
+  pub fn history(&self) -> &History {
+    &self.history
+  }
+
+  pub fn add_text(&mut self, text: &str) -> Result<(), PostError> {
+    if !self.state().can_edit() {
+      return Err(PostError::NotEditable {
+        state: self.state_name(),
+      });
+    }
+
+    self.content.push_str(text);
+    Ok(())
+  }
+
+  pub fn content(&self) -> &str {
+    self.state().content(self)
+  }
+
+  /// Takes `action` on behalf of `actor` and records it in the history.
+  ///
+  /// Transitions that fail leave no trace in the history.
+  pub fn apply(
+    &mut self,
+    action: Action,
+    actor: &str,
+    comment: Option<&str>,
+  ) -> Result<(), PostError> {
+    // Option::take returns the Option and
+    // leaves None in its place.
+    let state = self.state.take().unwrap();
+    let from = state.kind();
+
+    match action.apply(state, actor) {
+      Ok(next) => {
+        self.history.push(Event {
+          action,
+          actor: actor.to_string(),
+          timestamp: SystemTime::now(),
+          from,
+          to: next.kind(),
+          comment: comment.map(String::from),
+        });
+
+        self.state = Some(next);
+        Ok(())
+      }
+      Err((state, error)) => {
+        self.state = Some(state);
+        Err(error)
+      }
+    }
+  }
+
+  pub fn request_review(&mut self, actor: &str) -> Result<(), PostError> {
+    self.apply(Action::RequestReview, actor, None)
+  }
+
+  pub fn approve(&mut self, approver: &str) -> Result<(), PostError> {
+    self.apply(Action::Approve, approver, None)
+  }
+
+  pub fn reject(&mut self, actor: &str) -> Result<(), PostError> {
+    self.apply(Action::Reject, actor, None)
+  }
+
+  /// Returns a copy of the post whose state was rebuilt by replaying
+  /// every event of the history on a new draft.
+  ///
+  /// Fails if the history doesn't lead to the state the post is in.
+  pub fn replay(&self) -> Result<Post, PostError> {
+    let mut state: Box<dyn State> = Box::new(Draft {
+      required_approvals: self.required_approvals,
+    });
+
+    for (index, event) in self.history.events().iter().enumerate() {
+      let inconsistent = PostError::InconsistentHistory { index };
+
+      if state.kind() != event.from {
+        return Err(inconsistent);
+      }
+
+      state = event
+        .action
+        .apply(state, &event.actor)
+        .map_err(|_| inconsistent.clone())?;
+
+      if state.kind() != event.to {
+        return Err(inconsistent);
+      }
+    }
+
+    if state.kind() != self.state_kind() {
+      return Err(PostError::IncompleteHistory {
+        state: self.state_name(),
+        replayed: state.name(),
+      });
+    }
+
+    Ok(Post {
+      state: Some(state),
+      content: self.content.clone(),
+      required_approvals: self.required_approvals,
+      history: self.history.clone(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejected_posts_go_back_to_draft() {
+    let mut post = Post::new();
+    post.add_text("draft").unwrap();
+    post.request_review("amy").unwrap();
+
+    assert_eq!(
+      Err(PostError::NotEditable {
+        state: "pending review"
+      }),
+      post.add_text("more")
+    );
+
+    post.reject("bob").unwrap();
+    assert_eq!("draft", post.state_name());
+    assert_eq!(Ok(()), post.add_text(", edited"));
+  }
+
+  #[test]
+  fn needs_every_approval() {
+    let mut post = Post::with_required_approvals(2);
+    post.add_text("text").unwrap();
+    post.request_review("amy").unwrap();
+
+    post.approve("alice").unwrap();
+    assert_eq!(
+      Err(PostError::AlreadyApproved {
+        approver: "alice".to_string()
+      }),
+      post.approve("alice")
+    );
+    assert_eq!("pending review", post.state_name());
+
+    post.approve("bob").unwrap();
+    assert_eq!("text", post.content());
+  }
+
+  #[test]
+  fn illegal_transitions_keep_the_state() {
+    let mut post = Post::new();
+
+    assert_eq!(
+      Err(PostError::IllegalTransition {
+        state: "draft",
+        action: "approve"
+      }),
+      post.approve("alice")
+    );
+    assert_eq!("draft", post.state_name());
+  }
+
+  #[test]
+  fn records_and_replays_history() {
+    let mut post = Post::with_required_approvals(2);
+    post.request_review("amy").unwrap();
+    post
+      .apply(Action::Reject, "bob", Some("too short"))
+      .unwrap();
+    post.request_review("amy").unwrap();
+    post.approve("bob").unwrap();
+    assert!(post.approve("bob").is_err());
+    post.approve("carol").unwrap();
+
+    let history = post.history();
+    assert_eq!(5, history.events().len());
+    assert_eq!(
+      vec![Some("too short".to_string()), None],
+      history
+        .by_actor("bob")
+        .map(|event| event.comment.clone())
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(
+      "carol",
+      history.entering(StateKind::Published).next().unwrap().actor
+    );
+
+    let replayed = post.replay().unwrap();
+    assert_eq!(StateKind::Published, replayed.state_kind());
+    assert_eq!(post.history(), replayed.history());
+  }
+
+  #[test]
+  fn replay_detects_edited_history() {
+    let mut post = Post::new();
+    post.request_review("amy").unwrap();
+    post.approve("bob").unwrap();
+
+    post.history.events.remove(0);
+
+    assert_eq!(
+      Err(PostError::InconsistentHistory { index: 0 }),
+      post.replay().map(|post| post.state_kind())
+    );
+  }
+
+  #[test]
+  fn replay_needs_the_whole_history() {
+    let mut post = Post::new();
+    post.request_review("amy").unwrap();
+
+    // As if it had been saved before the history was kept.
+    post.history = History::default();
+
+    assert_eq!(
+      Err(PostError::IncompleteHistory {
+        state: "pending review",
+        replayed: "draft",
+      }),
+      post.replay().map(|post| post.state_kind())
+    );
+  }
+}
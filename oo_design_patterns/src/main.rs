@@ -1,128 +1,87 @@
-// Implementing an object-oriented design pattern
-//
-// The state pattern is an object-oriented design pattern.
-// The crux of the pattern if that a value has some internal state,
-// which is represented by a set of state objects, and the value's
-// behaviour changes based on the internal state.
-// The state objects share functionality.
-//
-// NOTE: can we replace the state pattern with type state?
-
-trait State {
-  // We use Box<Self> here because self is a trait,
-  // and it's size is not known, but the size of a
-  // Box<T> always is known.
-  fn request_review(self: Box<Self>) -> Box<dyn State>;
-
-  fn approve(self: Box<Self>) -> Box<dyn State>;
-
-  // The return value will live as long as [post].
-  fn content<'a>(&self, _post: &'a Post) -> &'a str {
-    ""
-  }
-}
-
-struct Draft {}
-
-impl State for Draft {
-  fn request_review(self: Box<Self>) -> Box<dyn State> {
-    Box::new(PendingReview {})
-  }
+// The blog post workflow from lib.rs, with posts
+// saved to disk between the steps.
+use oo_design_patterns::{Engine, Post, Repository, StateKind, Workflow};
 
-  fn approve(self: Box<Self>) -> Box<dyn State> {
-    self
-  }
-}
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  // A directory of its own for every run, so posts from earlier
+  // runs don't show up in the listing below.
+  let dir = std::env::temp_dir().join(format!("oo_design_patterns_posts-{}", std::process::id()));
+  let repository = Repository::open(&dir)?;
 
-struct PendingReview {}
+  let mut post = Post::with_required_approvals(2);
 
-impl State for PendingReview {
-  // This is an example of why this pattern is not very good
-  // if implemented in an OOP way,
-  // it clearly makes no sense for PendingReview to have a method
-  // called request_review but it has to have it because
-  // every state is implementing the same trait.
-  fn request_review(self: Box<Self>) -> Box<dyn State> {
-    self
-  }
+  post.add_text("I ate a salad for lunch today")?;
 
-  fn approve(self: Box<Self>) -> Box<dyn State> {
-    Box::new(Published {})
-  }
-}
+  // Why are we able to call a method while the post
+  // is in the draft state?
+  // Seems like type state is coming.
+  assert_eq!("", post.content());
 
-struct Published {}
+  post.request_review("amy")?;
 
-impl State for Published {
-  fn request_review(self: Box<Self>) -> Box<dyn State> {
-    self
-  }
+  assert_eq!("", post.content());
 
-  fn approve(self: Box<Self>) -> Box<dyn State> {
-    self
-  }
+  post.reject("bob")?;
+  post.add_text(" with croutons")?;
+  post.request_review("amy")?;
 
-  fn content<'a>(&self, post: &'a Post) -> &'a str {
-    &post.content
-  }
-}
+  post.approve("alice")?;
+  assert_eq!("", post.content());
 
-pub struct Post {
-  state: Option<Box<dyn State>>,
-  content: String,
-}
+  // The post is saved and loaded back, still waiting for a second approval.
+  let id = repository.insert(&post)?;
+  let mut post = repository.load(id)?;
 
-impl Post {
-  pub fn new() -> Self {
-    Self {
-      state: Some(Box::new(Draft {})),
-      content: String::new(),
-    }
-  }
+  post.approve("bob")?;
+  repository.save(id, &post)?;
 
-  pub fn add_text(&mut self, text: &str) {
-    self.content.push_str(text);
-  }
+  assert_eq!(
+    "I ate a salad for lunch today with croutons",
+    post.content()
+  );
 
-  pub fn content(&self) -> &str {
-    // Calling Option::as_ref because we want
-    // a reference to the value inside the Option.
-    // &Option<T>::as_ref returns Option<&T>.
-    self.state.as_ref().unwrap().content(self)
-  }
+  for (id, post) in repository.list_by_state(StateKind::Published)? {
+    println!("{}: {}", id, post.content());
 
-  pub fn approve(&mut self) {
-    // Option::take returns the Option and
-    // leaves None in its place.
-    if let Some(state) = self.state.take() {
-      self.state = Some(state.approve())
+    for event in post.history().events() {
+      println!(
+        "  {:?} by {}: {:?} -> {:?}",
+        event.action, event.actor, event.from, event.to
+      );
     }
   }
 
-  pub fn request_review(&mut self) {
-    // Option::take returns the Option and
-    // leaves None in its place.
-    if let Some(state) = self.state.take() {
-      self.state = Some(state.request_review())
-    }
+  std::fs::remove_dir_all(&dir)?;
+
+  // The same workflow, loaded as data and run by the engine.
+  let workflow: Workflow = serde_json::from_str(
+    r#"{
+      "initial": "draft",
+      "states": [
+        { "name": "draft" },
+        { "name": "pending review", "on_entry": ["notify"] },
+        { "name": "published", "terminal": true }
+      ],
+      "transitions": [
+        { "from": "draft", "event": "request_review", "to": "pending review" },
+        { "from": "pending review", "event": "approve", "to": "published", "guard": "has_content" },
+        { "from": "pending review", "event": "reject", "to": "draft" }
+      ]
+    }"#,
+  )?;
+  println!("{}", workflow.to_dot());
+
+  let engine = Engine::builder(workflow)
+    .guard("has_content", |content: &String| !content.is_empty())
+    .action("notify", |_| println!("  a post is waiting for review"))
+    .build()
+    .map_err(|errors| format!("invalid workflow: {:?}", errors))?;
+
+  let mut post = engine.start(String::from("I ate a salad for lunch today"));
+  for event in &["request_review", "approve"] {
+    engine.fire(&mut post, event)?;
+    println!("{} -> {}", event, post.state());
   }
-}
-
-fn main() {
-  let mut post = Post::new();
-
-  post.add_text("I ate a salad for lunch today");
-
-  // Why are we able to call a method while the post
-  // is in the draft state?
-  // Seems like type state is coming.
-  assert_eq!("", post.content());
-
-  post.request_review();
-
-  assert_eq!("", post.content());
-
-  post.approve();
 
-  assert_eq!("I ate a salad for lunch today", post.content());
+  Ok(())
 }
@@ -0,0 +1,393 @@
+// Saves posts to disk so they outlive the program.
+//
+// Every post is a JSON file named after its id inside the
+// repository's directory:
+//
+//   posts/1.json  {"content":"...","state":{"kind":"Draft","required_approvals":1},...}
+//   posts/2.json  {"content":"...","state":{"kind":"Published"},...}
+//
+// The history of the post is saved with it. The id the next post
+// gets is kept in posts/next_id, so ids of deleted posts aren't
+// handed out again.
+use std::{
+  error::Error,
+  fmt, fs, io,
+  path::{Path, PathBuf},
+  process,
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{History, Post, StateKind, StateRecord};
+
+pub type PostId = u64;
+
+const NEXT_ID: &str = "next_id";
+/// Held while an id is handed out and next_id updated.
+const LOCK: &str = "lock";
+
+/// Makes the names of temporary files unique within the process,
+/// the process id makes them unique between processes.
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+pub enum StoreError {
+  Io(io::Error),
+  NotFound(PostId),
+  /// The file of a post exists but doesn't hold a post.
+  Corrupt {
+    id: PostId,
+    error: serde_json::Error,
+  },
+}
+
+impl fmt::Display for StoreError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      StoreError::Io(error) => write!(f, "{}", error),
+      StoreError::NotFound(id) => write!(f, "there is no post {}", id),
+      StoreError::Corrupt { id, error } => write!(f, "post {} is corrupt: {}", id, error),
+    }
+  }
+}
+
+impl Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+  fn from(error: io::Error) -> Self {
+    StoreError::Io(error)
+  }
+}
+
+/// A post as it is saved to disk.
+#[derive(Serialize, Deserialize)]
+struct PostRecord {
+  content: String,
+  state: StateRecord,
+  // Posts saved before the history was kept have neither of these,
+  // their state still knows how many approvals they need.
+  #[serde(default)]
+  required_approvals: Option<usize>,
+  #[serde(default)]
+  history: History,
+}
+
+impl From<&Post> for PostRecord {
+  fn from(post: &Post) -> Self {
+    Self {
+      content: post.content.clone(),
+      state: post.state().to_record(),
+      required_approvals: Some(post.required_approvals),
+      history: post.history.clone(),
+    }
+  }
+}
+
+impl From<PostRecord> for Post {
+  fn from(record: PostRecord) -> Self {
+    // Published posts don't need approvals anymore, any number will do.
+    let required_approvals = record
+      .required_approvals
+      .or_else(|| record.state.required_approvals())
+      .unwrap_or(1);
+
+    Self {
+      state: Some(record.state.into_state()),
+      content: record.content,
+      required_approvals,
+      history: record.history,
+    }
+  }
+}
+
+pub struct Repository {
+  dir: PathBuf,
+}
+
+impl Repository {
+  /// Opens the repository in `dir`, creating the directory if needed.
+  pub fn open(dir: impl AsRef<Path>) -> Result<Self, StoreError> {
+    let dir = dir.as_ref().to_path_buf();
+    fs::create_dir_all(&dir)?;
+
+    Ok(Self { dir })
+  }
+
+  fn path(&self, id: PostId) -> PathBuf {
+    self.dir.join(format!("{}.json", id))
+  }
+
+  fn temp_path(&self) -> PathBuf {
+    let n = TEMP_FILES.fetch_add(1, Ordering::SeqCst);
+    self.dir.join(format!("{}-{}.tmp", process::id(), n))
+  }
+
+  /// Writes `contents` to `path` through a temporary file renamed
+  /// over it, so a crash never leaves half a file behind.
+  fn write_atomically(&self, path: &Path, contents: &str) -> Result<(), StoreError> {
+    let temp = self.temp_path();
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)?;
+
+    Ok(())
+  }
+
+  /// The id the next post gets, never one that was used before.
+  fn next_id(&self) -> Result<PostId, StoreError> {
+    let saved = match fs::read_to_string(self.dir.join(NEXT_ID)) {
+      Ok(id) => id.trim().parse().ok(),
+      Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+      Err(error) => return Err(error.into()),
+    };
+
+    // Repositories written before next_id existed don't have one.
+    let after_last = self.ids()?.last().map_or(1, |last| last + 1);
+
+    Ok(saved.map_or(after_last, |saved: PostId| saved.max(after_last)))
+  }
+
+  /// Returns the id of every saved post, in ascending order.
+  pub fn ids(&self) -> Result<Vec<PostId>, StoreError> {
+    let mut ids = Vec::new();
+
+    for entry in fs::read_dir(&self.dir)? {
+      let path = entry?.path();
+
+      // Other files, like the temporary ones written by save, are skipped.
+      if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+      {
+        if let Some(id) = path
+          .file_stem()
+          .and_then(|stem| stem.to_str()?.parse().ok())
+        {
+          ids.push(id);
+        }
+      }
+    }
+
+    ids.sort_unstable();
+    Ok(ids)
+  }
+
+  /// Saves `post` as a new post and returns its id.
+  ///
+  /// Safe to call from several threads or processes at once,
+  /// each post gets an id of its own.
+  pub fn insert(&self, post: &Post) -> Result<PostId, StoreError> {
+    let json = serde_json::to_string(&PostRecord::from(post)).unwrap();
+
+    let temp = self.temp_path();
+    fs::write(&temp, json)?;
+
+    // Only one insert at a time reads and writes next_id, otherwise a
+    // slower insert could save a smaller next_id over a bigger one.
+    // The OS drops the lock when the file is closed, even on a crash.
+    let lock = fs::OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(self.dir.join(LOCK))?;
+    lock.lock()?;
+
+    // Hard linking fails if the file already exists, so when a post was
+    // saved with the id some other way we move on to the next one. Unlike
+    // creating the file and then writing it, the post appears whole.
+    let mut id = self.next_id()?;
+
+    let linked = loop {
+      match fs::hard_link(&temp, self.path(id)) {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => id += 1,
+        result => break result,
+      }
+    };
+
+    fs::remove_file(&temp)?;
+    linked?;
+
+    self.write_atomically(&self.dir.join(NEXT_ID), &(id + 1).to_string())?;
+
+    Ok(id)
+  }
+
+  /// Saves `post` under `id`, replacing what was saved there before.
+  pub fn save(&self, id: PostId, post: &Post) -> Result<(), StoreError> {
+    // Serializing a String and an enum of plain fields can't fail.
+    let json = serde_json::to_string(&PostRecord::from(post)).unwrap();
+
+    self.write_atomically(&self.path(id), &json)
+  }
+
+  pub fn load(&self, id: PostId) -> Result<Post, StoreError> {
+    let json = match fs::read_to_string(self.path(id)) {
+      Ok(json) => json,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => {
+        return Err(StoreError::NotFound(id))
+      }
+      Err(error) => return Err(error.into()),
+    };
+
+    let record: PostRecord =
+      serde_json::from_str(&json).map_err(|error| StoreError::Corrupt { id, error })?;
+
+    Ok(Post::from(record))
+  }
+
+  pub fn delete(&self, id: PostId) -> Result<(), StoreError> {
+    match fs::remove_file(self.path(id)) {
+      Err(error) if error.kind() == io::ErrorKind::NotFound => Err(StoreError::NotFound(id)),
+      result => Ok(result?),
+    }
+  }
+
+  /// Returns every post that is in the `kind` state, by id.
+  pub fn list_by_state(&self, kind: StateKind) -> Result<Vec<(PostId, Post)>, StoreError> {
+    let mut posts = Vec::new();
+
+    for id in self.ids()? {
+      let post = self.load(id)?;
+
+      if post.state_kind() == kind {
+        posts.push((id, post));
+      }
+    }
+
+    Ok(posts)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn repository(name: &str) -> Repository {
+    let dir = std::env::temp_dir().join(format!("posts-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    Repository::open(dir).unwrap()
+  }
+
+  #[test]
+  fn states_survive_a_round_trip() {
+    let repository = repository("round-trip");
+
+    let mut post = Post::with_required_approvals(2);
+    post.add_text("salad").unwrap();
+    post.request_review("amy").unwrap();
+    post.approve("alice").unwrap();
+
+    let id = repository.insert(&post).unwrap();
+    let mut loaded = repository.load(id).unwrap();
+
+    assert_eq!(StateKind::PendingReview, loaded.state_kind());
+    // alice's approval was saved too.
+    assert!(loaded.approve("alice").is_err());
+
+    loaded.approve("bob").unwrap();
+    repository.save(id, &loaded).unwrap();
+
+    let loaded = repository.load(id).unwrap();
+    assert_eq!("salad", loaded.content());
+    assert_eq!(3, loaded.history().events().len());
+
+    fs::remove_dir_all(&repository.dir).unwrap();
+  }
+
+  #[test]
+  fn lists_posts_by_state() {
+    let repository = repository("by-state");
+
+    let draft = Post::new();
+    let mut published = Post::new();
+    published.request_review("amy").unwrap();
+    published.approve("alice").unwrap();
+
+    let draft_id = repository.insert(&draft).unwrap();
+    let published_id = repository.insert(&published).unwrap();
+
+    let ids = |kind| -> Vec<PostId> {
+      let posts = repository.list_by_state(kind).unwrap();
+      posts.into_iter().map(|(id, _)| id).collect()
+    };
+
+    assert_eq!(vec![draft_id], ids(StateKind::Draft));
+    assert_eq!(vec![published_id], ids(StateKind::Published));
+    assert!(ids(StateKind::PendingReview).is_empty());
+
+    repository.delete(draft_id).unwrap();
+    assert!(matches!(
+      repository.load(draft_id),
+      Err(StoreError::NotFound(id)) if id == draft_id
+    ));
+
+    fs::remove_dir_all(&repository.dir).unwrap();
+  }
+
+  #[test]
+  fn reads_posts_saved_without_history() {
+    let repository = repository("old");
+
+    fs::write(
+      repository.path(1),
+      r#"{"content":"soup","state":{"kind":"PendingReview","approvers":["alice"],"required_approvals":3}}"#,
+    )
+    .unwrap();
+
+    let mut post = repository.load(1).unwrap();
+
+    // There is no history that leads to this state.
+    assert!(matches!(
+      post.replay(),
+      Err(crate::PostError::IncompleteHistory { .. })
+    ));
+
+    // The state says how many approvals are needed, not a default.
+    post.approve("bob").unwrap();
+    assert_eq!(StateKind::PendingReview, post.state_kind());
+
+    post.approve("carol").unwrap();
+    assert_eq!(StateKind::Published, post.state_kind());
+
+    fs::remove_dir_all(&repository.dir).unwrap();
+  }
+
+  #[test]
+  fn ids_are_never_reused() {
+    let repository = repository("ids");
+
+    let first = repository.insert(&Post::new()).unwrap();
+    let second = repository.insert(&Post::new()).unwrap();
+    repository.delete(second).unwrap();
+
+    assert_eq!(second + 1, repository.insert(&Post::new()).unwrap());
+
+    // Concurrent inserts all get ids of their own.
+    let repository = std::sync::Arc::new(repository);
+    let threads: Vec<_> = (0..8)
+      .map(|_| {
+        let repository = std::sync::Arc::clone(&repository);
+        std::thread::spawn(move || repository.insert(&Post::new()).unwrap())
+      })
+      .collect();
+
+    let mut ids: Vec<PostId> = threads
+      .into_iter()
+      .map(|thread| thread.join().unwrap())
+      .collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    assert_eq!(8, ids.len());
+    assert_eq!(10, repository.ids().unwrap().len());
+    assert!(!ids.contains(&first));
+
+    // next_id ends after the last id handed out, no matter which
+    // insert finished last.
+    let next_id = fs::read_to_string(repository.dir.join(NEXT_ID)).unwrap();
+    assert_eq!(ids.last().unwrap() + 1, next_id.parse::<PostId>().unwrap());
+
+    fs::remove_dir_all(&repository.dir).unwrap();
+  }
+}
@@ -0,0 +1,222 @@
+// #[derive(Builder)]: the builder pattern without the boilerplate.
+//
+//   #[derive(Builder)]
+//   struct Command {
+//     executable: String,
+//     #[builder(default)]
+//     args: Vec<String>,
+//     current_dir: Option<String>,
+//   }
+//
+// generates a CommandBuilder with a setter per field:
+//
+//   let command = Command::builder()
+//     .executable("cargo".to_string())
+//     .current_dir("..".to_string())
+//     .build()?;
+//
+// Fields are required unless they are marked #[builder(default)],
+// in which case Default::default() is used when they aren't set,
+// or are an Option, which is None when they aren't set. The setter
+// of an Option<T> takes a T.
+//
+// build() returns a CommandBuilderError naming every required field
+// that wasn't set.
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+  spanned::Spanned, Data, DeriveInput, Field, Fields, GenericArgument, Meta, NestedMeta,
+  PathArguments, Type,
+};
+
+enum Kind<'a> {
+  Required,
+  Default,
+  /// An Option<T>, holding T.
+  Optional(&'a Type),
+}
+
+/// The T of an Option<T>, if `ty` is one.
+fn option_inner(ty: &Type) -> Option<&Type> {
+  let path = match ty {
+    Type::Path(ty) if ty.qself.is_none() => &ty.path,
+    _ => return None,
+  };
+
+  let segment = path.segments.last()?;
+  if segment.ident != "Option" {
+    return None;
+  }
+
+  match &segment.arguments {
+    PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+      GenericArgument::Type(inner) => Some(inner),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+fn kind(field: &Field) -> syn::Result<Kind<'_>> {
+  let mut default = false;
+
+  for attr in field
+    .attrs
+    .iter()
+    .filter(|attr| attr.path.is_ident("builder"))
+  {
+    let list = match attr.parse_meta()? {
+      Meta::List(list) => list,
+      meta => return Err(syn::Error::new(meta.span(), "expected #[builder(default)]")),
+    };
+
+    for nested in &list.nested {
+      match nested {
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => default = true,
+        _ => {
+          return Err(syn::Error::new(
+            nested.span(),
+            "unknown builder option, the only one is `default`",
+          ))
+        }
+      }
+    }
+  }
+
+  Ok(match option_inner(&field.ty) {
+    // An Option is already optional, #[builder(default)] changes nothing.
+    Some(inner) => Kind::Optional(inner),
+    None if default => Kind::Default,
+    None => Kind::Required,
+  })
+}
+
+pub fn expand(ast: &DeriveInput) -> syn::Result<TokenStream> {
+  let name = &ast.ident;
+  let vis = &ast.vis;
+
+  let fields = match &ast.data {
+    Data::Struct(data) => match &data.fields {
+      Fields::Named(fields) => &fields.named,
+      fields => {
+        return Err(syn::Error::new(
+          fields.span(),
+          "Builder needs named fields to name the setters after",
+        ))
+      }
+    },
+    _ => {
+      return Err(syn::Error::new(
+        name.span(),
+        "Builder can only be derived for structs",
+      ))
+    }
+  };
+
+  let builder = format_ident!("{}Builder", name);
+  let error = format_ident!("{}BuilderError", name);
+  let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+  let mut builder_fields = Vec::new();
+  let mut setters = Vec::new();
+  let mut checks = Vec::new();
+  let mut values = Vec::new();
+
+  for field in fields {
+    let ident = field.ident.as_ref().unwrap();
+    let ty = &field.ty;
+    let field_name = ident.to_string();
+
+    match kind(field)? {
+      Kind::Optional(inner) => {
+        builder_fields.push(quote! { #ident: #ty });
+        setters.push(quote! {
+          pub fn #ident(mut self, #ident: #inner) -> Self {
+            self.#ident = Some(#ident);
+            self
+          }
+        });
+        values.push(quote! { #ident: self.#ident });
+      }
+      kind => {
+        builder_fields.push(quote! { #ident: Option<#ty> });
+        setters.push(quote! {
+          pub fn #ident(mut self, #ident: #ty) -> Self {
+            self.#ident = Some(#ident);
+            self
+          }
+        });
+
+        if let Kind::Required = kind {
+          checks.push(quote! {
+            if self.#ident.is_none() {
+              missing.push(#field_name);
+            }
+          });
+          // Every missing field was reported above.
+          values.push(quote! { #ident: self.#ident.unwrap() });
+        } else {
+          values.push(quote! { #ident: self.#ident.unwrap_or_default() });
+        }
+      }
+    }
+  }
+
+  let idents = fields.iter().map(|field| &field.ident);
+  let struct_name = name.to_string();
+  let builder_doc = format!("Builds a [`{}`], see [`{}::builder`].", name, name);
+  let error_doc = format!("The required fields of [`{}`] that weren't set.", name);
+
+  Ok(quote! {
+    #[doc = #builder_doc]
+    #vis struct #builder #impl_generics #where_clause {
+      #(#builder_fields),*
+    }
+
+    #[doc = #error_doc]
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #vis struct #error {
+      pub missing: Vec<&'static str>,
+    }
+
+    impl std::fmt::Display for #error {
+      fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+          f,
+          "can't build {}, {} {} not set",
+          #struct_name,
+          self.missing.join(", "),
+          if self.missing.len() == 1 { "was" } else { "were" }
+        )
+      }
+    }
+
+    impl std::error::Error for #error {}
+
+    impl #impl_generics #name #ty_generics #where_clause {
+      pub fn builder() -> #builder #ty_generics {
+        #builder {
+          #(#idents: None),*
+        }
+      }
+    }
+
+    impl #impl_generics #builder #ty_generics #where_clause {
+      #(#setters)*
+
+      pub fn build(self) -> Result<#name #ty_generics, #error> {
+        #[allow(unused_mut)]
+        let mut missing = Vec::new();
+        #(#checks)*
+
+        if !missing.is_empty() {
+          return Err(#error { missing });
+        }
+
+        Ok(#name {
+          #(#values),*
+        })
+      }
+    }
+  })
+}
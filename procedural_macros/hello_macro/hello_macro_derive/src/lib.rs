@@ -1,27 +1,104 @@
 extern crate proc_macro;
 
+mod builder;
+mod state_machine;
+
 use proc_macro::TokenStream;
 use quote::quote;
-use syn;
 
-#[proc_macro_derive(HelloMacro)]
+/// Implements HelloMacro, printing the name of the type
+/// or the one given with #[hello(name = "...")].
+#[proc_macro_derive(HelloMacro, attributes(hello))]
 pub fn hello_macro_drive(input: TokenStream) -> TokenStream {
   // Build ast from Rust source code to make manipulation easier.
-  let ast = syn::parse(input).unwrap();
+  // parse_macro_input! returns the parse error as a compile_error!
+  // instead of panicking.
+  let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
   impl_hello_macro(&ast)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+/// The name given with #[hello(name = "...")], if any.
+fn hello_name(ast: &syn::DeriveInput) -> syn::Result<Option<String>> {
+  let mut name = None;
+
+  for attr in ast.attrs.iter().filter(|attr| attr.path.is_ident("hello")) {
+    let list = match attr.parse_meta()? {
+      syn::Meta::List(list) => list,
+      meta => {
+        return Err(syn::Error::new_spanned(
+          meta,
+          "expected #[hello(name = \"...\")]",
+        ))
+      }
+    };
+
+    for nested in &list.nested {
+      match nested {
+        syn::NestedMeta::Meta(syn::Meta::NameValue(pair)) if pair.path.is_ident("name") => {
+          match &pair.lit {
+            syn::Lit::Str(lit) if name.is_none() => name = Some(lit.value()),
+            syn::Lit::Str(lit) => {
+              return Err(syn::Error::new_spanned(
+                lit,
+                "the name is given more than once",
+              ))
+            }
+            lit => return Err(syn::Error::new_spanned(lit, "the name must be a string")),
+          }
+        }
+        _ => {
+          return Err(syn::Error::new_spanned(
+            nested,
+            "unknown hello option, the only one is `name = \"...\"`",
+          ))
+        }
+      }
+    }
+  }
+
+  Ok(name)
 }
 
-fn impl_hello_macro(ast: &syn::DeriveInput) -> TokenStream {
+fn impl_hello_macro(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
   let name = &ast.ident;
+  let hello_name = hello_name(ast)?.unwrap_or_else(|| name.to_string());
+
+  // Wrapper<T> gets impl<T: Bound> HelloMacro for Wrapper<T> where ...
+  let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
 
   let gen = quote! {
-    impl HelloMacro for #name {
+    impl #impl_generics HelloMacro for #name #ty_generics #where_clause {
       fn hello_macro() {
-        println!("Hello, Macro! My name is {}!",stringify!(#name));
+        println!("Hello, Macro! My name is {}!", #hello_name);
       }
     }
   };
 
-  gen.into()
+  Ok(gen)
+}
+
+/// Generates a typestate machine, and its runtime equivalent,
+/// from the variants of an enum and a #[state_machine(...)] table.
+/// See state_machine.rs for the syntax.
+#[proc_macro_derive(StateMachine, attributes(state_machine))]
+pub fn state_machine_derive(input: TokenStream) -> TokenStream {
+  let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+
+  // Errors become compile_error!s pointing at the offending tokens.
+  state_machine::expand(&ast)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+/// Generates a builder with a setter per field, see builder.rs.
+#[proc_macro_derive(Builder, attributes(builder))]
+pub fn builder_derive(input: TokenStream) -> TokenStream {
+  let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+
+  builder::expand(&ast)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
 }
@@ -0,0 +1,548 @@
+// #[derive(StateMachine)]: typestate machines from a transition table.
+//
+// Writing a typestate machine by hand, as in type_state, means one
+// struct per state and one impl block per transition. The derive
+// takes the states from the variants of an enum and the transitions
+// from a table instead:
+//
+//   #[derive(StateMachine)]
+//   #[state_machine(
+//     data = Article,
+//     Draft => request_review => PendingReview,
+//     PendingReview => approve if has_title => Published,
+//     PendingReview => reject => Draft,
+//   )]
+//   enum Post { Draft, PendingReview, Published }
+//
+// Each entry reads "in state From, the event leads to To", and `if guard`
+// names a `fn(&Data) -> bool` that must hold for the transition to happen.
+// `data` is the type carried from state to state, `()` when left out.
+// The first variant is the initial state.
+//
+// Everything is generated inside a module named after the enum,
+// `post` here, with the visibility of the enum:
+//
+//   post::Draft, post::PendingReview, ...  a zero-sized type per state
+//   post::Machine<S>                        the machine in the state S, with
+//                                           a method per transition out of S
+//   post::Event                             an enum with a variant per event
+//   post::Dynamic                           the same machine checked at runtime,
+//                                           for when the state isn't known
+//                                           at compile time
+//
+// The module starts with `use super::*`, so the data type and the guards
+// are looked up where the enum is defined, which must be a module and
+// not the body of a function.
+use std::collections::HashSet;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+  ext::IdentExt,
+  parse::{Parse, ParseStream},
+  punctuated::Punctuated,
+  spanned::Spanned,
+  Data, DeriveInput, Fields, Ident, Path, Token, Type,
+};
+
+/// An entry of the #[state_machine(...)] table.
+enum Entry {
+  Data(Type),
+  Transition(Transition),
+}
+
+struct Transition {
+  from: Ident,
+  event: Ident,
+  guard: Option<Path>,
+  to: Ident,
+}
+
+impl Parse for Entry {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let first: Ident = input.parse()?;
+
+    // `=` also matches the start of `=>`, so `=>` has to be checked first.
+    if !input.peek(Token![=>]) && input.peek(Token![=]) {
+      if first != "data" {
+        return Err(syn::Error::new(
+          first.span(),
+          format!("unknown option `{}`, expected `data = Type`", first),
+        ));
+      }
+
+      input.parse::<Token![=]>()?;
+      return Ok(Entry::Data(input.parse()?));
+    }
+
+    input.parse::<Token![=>]>()?;
+    let event = input.parse()?;
+
+    let guard = if input.peek(Token![if]) {
+      input.parse::<Token![if]>()?;
+      Some(input.parse()?)
+    } else {
+      None
+    };
+
+    input.parse::<Token![=>]>()?;
+    let to = input.parse()?;
+
+    Ok(Entry::Transition(Transition {
+      from: first,
+      event,
+      guard,
+      to,
+    }))
+  }
+}
+
+/// Methods every Machine has, so they can't be event names.
+const RESERVED: [&str; 4] = ["new", "state", "into_dynamic", "into_state"];
+
+/// `PendingReview` -> `pending_review`, `HTTPState` -> `http_state`.
+///
+/// A run of capitals is one word, the last of them starts the
+/// next word when a lowercase letter follows it.
+fn snake_case(name: &str) -> String {
+  let chars: Vec<char> = name.chars().collect();
+  let mut snake = String::new();
+
+  for (i, &c) in chars.iter().enumerate() {
+    if c.is_uppercase() {
+      let after_lowercase = i > 0 && !chars[i - 1].is_uppercase();
+      let ends_run =
+        i > 0 && chars[i - 1].is_uppercase() && chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+
+      if after_lowercase || ends_run {
+        snake.push('_');
+      }
+      snake.extend(c.to_lowercase());
+    } else {
+      snake.push(c);
+    }
+  }
+
+  snake
+}
+
+/// `request_review` -> `RequestReview`.
+fn camel_case(name: &str) -> String {
+  name
+    .split('_')
+    .filter(|word| !word.is_empty())
+    .map(|word| {
+      let mut chars = word.chars();
+      match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+      }
+    })
+    .collect()
+}
+
+pub fn expand(ast: &DeriveInput) -> syn::Result<TokenStream> {
+  let name = &ast.ident;
+
+  let variants = match &ast.data {
+    Data::Enum(data) => &data.variants,
+    _ => {
+      return Err(syn::Error::new(
+        name.span(),
+        "StateMachine can only be derived for enums",
+      ))
+    }
+  };
+
+  if let Some(variant) = variants.iter().find(|v| !matches!(v.fields, Fields::Unit)) {
+    return Err(syn::Error::new(
+      variant.span(),
+      "states can't have fields, the data goes in #[state_machine(data = Type)]",
+    ));
+  }
+
+  let states: Vec<&Ident> = variants.iter().map(|v| &v.ident).collect();
+
+  let initial = match states.first() {
+    Some(initial) => *initial,
+    None => {
+      return Err(syn::Error::new(
+        name.span(),
+        "a state machine needs at least one state",
+      ))
+    }
+  };
+
+  let attr = ast
+    .attrs
+    .iter()
+    .find(|attr| attr.path.is_ident("state_machine"))
+    .ok_or_else(|| {
+      syn::Error::new(
+        name.span(),
+        "missing the transition table, add #[state_machine(From => event => To, ...)]",
+      )
+    })?;
+
+  let entries = attr.parse_args_with(Punctuated::<Entry, Token![,]>::parse_terminated)?;
+
+  let mut data = None;
+  let mut transitions = Vec::new();
+
+  for entry in entries {
+    match entry {
+      Entry::Data(ty) if data.is_some() => {
+        return Err(syn::Error::new(ty.span(), "`data` is given more than once"));
+      }
+      Entry::Data(ty) => data = Some(ty),
+      Entry::Transition(transition) => transitions.push(transition),
+    }
+  }
+
+  if transitions.is_empty() {
+    return Err(syn::Error::new(
+      attr.span(),
+      "the transition table is empty",
+    ));
+  }
+
+  let data = data.unwrap_or_else(|| syn::parse_quote!(()));
+
+  let state_list = states
+    .iter()
+    .map(|state| state.to_string())
+    .collect::<Vec<_>>()
+    .join(", ");
+
+  let mut seen = HashSet::new();
+
+  for transition in &transitions {
+    if RESERVED.iter().any(|reserved| transition.event == reserved) {
+      return Err(syn::Error::new(
+        transition.event.span(),
+        format!(
+          "`{}` can't be an event, the machine already has a method with that name",
+          transition.event
+        ),
+      ));
+    }
+
+    for state in &[&transition.from, &transition.to] {
+      if !states.contains(state) {
+        return Err(syn::Error::new(
+          state.span(),
+          format!(
+            "unknown state `{}`, the states of `{}` are: {}",
+            state, name, state_list
+          ),
+        ));
+      }
+    }
+
+    if !seen.insert((transition.from.to_string(), transition.event.to_string())) {
+      return Err(syn::Error::new(
+        transition.event.span(),
+        format!(
+          "`{}` already has a transition from `{}`",
+          transition.event, transition.from
+        ),
+      ));
+    }
+  }
+
+  // `enum Match` would need a module named `match`. Parsing the
+  // name as an identifier rejects every keyword.
+  let module_name = snake_case(&name.unraw().to_string());
+  if syn::parse_str::<Ident>(&module_name).is_err() {
+    return Err(syn::Error::new(
+      name.span(),
+      format!(
+        "the machine of `{}` goes in a module named `{}`, which is a keyword, rename the enum",
+        name, module_name
+      ),
+    ));
+  }
+  let module = Ident::new(&module_name, name.span());
+
+  // The Event variants, and the names the events were given in the table.
+  let mut events: Vec<Ident> = Vec::new();
+  let mut event_names: Vec<String> = Vec::new();
+  for transition in &transitions {
+    let name = transition.event.to_string();
+    let event = Ident::new(&camel_case(&name), transition.event.span());
+
+    match events.iter().position(|e| *e == event) {
+      None => {
+        events.push(event);
+        event_names.push(name);
+      }
+      // The same event out of another state.
+      Some(i) if event_names[i] == name => {}
+      Some(i) => {
+        return Err(syn::Error::new(
+          transition.event.span(),
+          format!(
+            "`{}` and `{}` would both be the event `{}`, rename one of them",
+            event_names[i], name, event
+          ),
+        ))
+      }
+    }
+  }
+
+  // Typestate: a method per transition, on the machine in the state it starts from.
+  let methods = transitions.iter().map(|t| {
+    let Transition {
+      from,
+      event,
+      guard,
+      to,
+    } = t;
+
+    let doc = format!("`{}` -> `{}`.", from, to);
+
+    match guard {
+      None => quote! {
+        impl Machine<#from> {
+          #[doc = #doc]
+          pub fn #event(self) -> Machine<#to> {
+            self.into_state()
+          }
+        }
+      },
+      Some(guard) => {
+        let doc = format!(
+          "`{}` -> `{}` if `{}` holds, otherwise the machine is given back.",
+          from,
+          to,
+          quote!(#guard)
+        );
+
+        quote! {
+          impl Machine<#from> {
+            #[doc = #doc]
+            pub fn #event(self) -> Result<Machine<#to>, Self> {
+              if #guard(&self.data) {
+                Ok(self.into_state())
+              } else {
+                Err(self)
+              }
+            }
+          }
+        }
+      }
+    }
+  });
+
+  // Runtime: the same table as a match.
+  let arms = transitions.iter().map(|t| {
+    let Transition {
+      from,
+      event,
+      guard,
+      to,
+    } = t;
+    let variant = format_ident!("{}", camel_case(&event.to_string()), span = event.span());
+
+    match guard {
+      None => quote! {
+        (#name::#from, Event::#variant) => #name::#to,
+      },
+      Some(guard) => {
+        let guard_name = quote!(#guard).to_string();
+        quote! {
+          (#name::#from, Event::#variant) => {
+            if !#guard(&self.data) {
+              return Err(TransitionError {
+                state: state_name(&self.state),
+                event,
+                guard: Some(#guard_name),
+              });
+            }
+            #name::#to
+          }
+        }
+      }
+    }
+  });
+
+  let legal_events = states.iter().map(|state| {
+    let from_state: Vec<Ident> = transitions
+      .iter()
+      .filter(|t| t.from == **state)
+      .map(|t| format_ident!("{}", camel_case(&t.event.to_string())))
+      .collect();
+
+    quote! { #name::#state => &[#(Event::#from_state),*], }
+  });
+
+  let state_names = states.iter().map(|state| state.to_string());
+
+  let machine_doc = format!(
+    "`{}` in the state `S`, the only transitions available are the ones out of `S`.",
+    name
+  );
+  let dynamic_doc = format!(
+    "`{}` with the state checked at runtime instead of by the compiler.",
+    name
+  );
+  let module_doc = format!("The state machine generated for [`{}`].", name);
+  let vis = &ast.vis;
+
+  Ok(quote! {
+    #[doc = #module_doc]
+    #[allow(dead_code)]
+    #vis mod #module {
+      use super::*;
+      use std::marker::PhantomData;
+
+      #(
+        #[derive(Debug)]
+        pub struct #states;
+      )*
+
+      /// Implemented by every state, so the machine can tell which one it is in.
+      pub trait State {
+        const STATE: #name;
+      }
+
+      #(
+        impl State for #states {
+          const STATE: #name = #name::#states;
+        }
+      )*
+
+      #[doc = #machine_doc]
+      pub struct Machine<S: State> {
+        pub data: #data,
+        state: PhantomData<S>,
+      }
+
+      impl Machine<#initial> {
+        pub fn new(data: #data) -> Self {
+          Machine {
+            data,
+            state: PhantomData,
+          }
+        }
+      }
+
+      impl<S: State> Machine<S> {
+        pub fn state(&self) -> #name {
+          S::STATE
+        }
+
+        pub fn into_dynamic(self) -> Dynamic {
+          Dynamic {
+            state: S::STATE,
+            data: self.data,
+          }
+        }
+
+        fn into_state<T: State>(self) -> Machine<T> {
+          Machine {
+            data: self.data,
+            state: PhantomData,
+          }
+        }
+      }
+
+      #(#methods)*
+
+      #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+      pub enum Event {
+        #(#events),*
+      }
+
+      impl Event {
+        pub fn name(&self) -> &'static str {
+          match self {
+            #(Event::#events => #event_names),*
+          }
+        }
+      }
+
+      #[derive(Debug, Clone, PartialEq, Eq)]
+      pub struct TransitionError {
+        pub state: &'static str,
+        pub event: Event,
+        /// The guard that didn't hold, None if the state has no transition for the event.
+        pub guard: Option<&'static str>,
+      }
+
+      impl std::fmt::Display for TransitionError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+          match self.guard {
+            Some(guard) => write!(
+              f,
+              "{} from {} is not allowed: {} doesn't hold",
+              self.event.name(),
+              self.state,
+              guard
+            ),
+            None => write!(
+              f,
+              "{} has no transition for {}",
+              self.state,
+              self.event.name()
+            ),
+          }
+        }
+      }
+
+      impl std::error::Error for TransitionError {}
+
+      fn state_name(state: &#name) -> &'static str {
+        match state {
+          #(#name::#states => #state_names),*
+        }
+      }
+
+      #[doc = #dynamic_doc]
+      pub struct Dynamic {
+        state: #name,
+        pub data: #data,
+      }
+
+      impl Dynamic {
+        pub fn new(data: #data) -> Self {
+          Machine::new(data).into_dynamic()
+        }
+
+        pub fn state(&self) -> &#name {
+          &self.state
+        }
+
+        pub fn state_name(&self) -> &'static str {
+          state_name(&self.state)
+        }
+
+        /// The events with a transition out of the current state, guarded or not.
+        pub fn events(&self) -> &'static [Event] {
+          match self.state {
+            #(#legal_events)*
+          }
+        }
+
+        /// Moves to the next state, or leaves the machine as it was
+        /// if the transition doesn't exist or its guard doesn't hold.
+        pub fn fire(&mut self, event: Event) -> Result<(), TransitionError> {
+          #[allow(unreachable_patterns)]
+          let next = match (&self.state, event) {
+            #(#arms)*
+            _ => {
+              return Err(TransitionError {
+                state: state_name(&self.state),
+                event,
+                guard: None,
+              })
+            }
+          };
+
+          self.state = next;
+          Ok(())
+        }
+      }
+    }
+  })
+}
@@ -0,0 +1,78 @@
+use hello_macro_derive::Builder;
+
+#[derive(Debug, PartialEq, Builder)]
+pub struct Command {
+  executable: String,
+  #[builder(default)]
+  args: Vec<String>,
+  current_dir: Option<String>,
+  timeout: u64,
+}
+
+#[derive(Debug, PartialEq, Builder)]
+struct Pair<T: Clone>
+where
+  T: PartialEq,
+{
+  left: T,
+  right: T,
+}
+
+#[test]
+fn builds_with_every_field_set() {
+  let command = Command::builder()
+    .executable("cargo".to_string())
+    .args(vec!["build".to_string()])
+    .current_dir("..".to_string())
+    .timeout(10)
+    .build()
+    .unwrap();
+
+  assert_eq!(
+    Command {
+      executable: "cargo".to_string(),
+      args: vec!["build".to_string()],
+      current_dir: Some("..".to_string()),
+      timeout: 10,
+    },
+    command
+  );
+}
+
+#[test]
+fn optional_fields_can_be_left_out() {
+  let command = Command::builder()
+    .executable("ls".to_string())
+    .timeout(1)
+    .build()
+    .unwrap();
+
+  assert!(command.args.is_empty());
+  assert_eq!(None, command.current_dir);
+}
+
+#[test]
+fn reports_every_missing_field() {
+  let error = Command::builder().args(Vec::new()).build().unwrap_err();
+
+  assert_eq!(vec!["executable", "timeout"], error.missing);
+  assert_eq!(
+    "can't build Command, executable, timeout were not set",
+    error.to_string()
+  );
+}
+
+#[test]
+fn generic_structs() {
+  let pair = Pair::builder().left(1).right(2).build().unwrap();
+  assert_eq!(Pair { left: 1, right: 2 }, pair);
+
+  let error = Pair::<u8>::builder().left(1).build().unwrap_err();
+  assert_eq!("can't build Pair, right was not set", error.to_string());
+}
+
+#[test]
+fn ui() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/builder_*.rs");
+}
@@ -0,0 +1,39 @@
+use std::fmt::Debug;
+
+use hello_macro::HelloMacro;
+use hello_macro_derive::HelloMacro;
+
+#[derive(HelloMacro)]
+struct Pancakes;
+
+#[derive(HelloMacro)]
+#[hello(name = "Flapjacks")]
+struct Hotcakes;
+
+#[derive(HelloMacro)]
+struct Stack<'a, T: Clone, const N: usize>
+where
+  T: Debug,
+{
+  pancakes: [&'a T; N],
+}
+
+fn hello<T: HelloMacro>() {
+  T::hello_macro();
+}
+
+#[test]
+fn derives_for_plain_and_generic_types() {
+  hello::<Pancakes>();
+  hello::<Hotcakes>();
+  hello::<Stack<'static, String, 3>>();
+
+  let stack = Stack { pancakes: [&1] };
+  assert_eq!(1, stack.pancakes.len());
+}
+
+#[test]
+fn ui() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/hello_*.rs");
+}
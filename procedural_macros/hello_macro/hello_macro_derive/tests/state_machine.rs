@@ -0,0 +1,120 @@
+use hello_macro_derive::StateMachine;
+
+pub struct Article {
+  title: String,
+}
+
+fn has_title(article: &Article) -> bool {
+  !article.title.is_empty()
+}
+
+#[derive(Debug, PartialEq, StateMachine)]
+#[state_machine(
+  data = Article,
+  Draft => request_review => PendingReview,
+  PendingReview => approve if has_title => Published,
+  PendingReview => reject => Draft,
+)]
+pub enum Post {
+  Draft,
+  PendingReview,
+  Published,
+}
+
+fn article(title: &str) -> Article {
+  Article {
+    title: title.to_string(),
+  }
+}
+
+#[test]
+fn typestate_transitions() {
+  let post = post::Machine::new(article("Salad"));
+  assert_eq!(Post::Draft, post.state());
+
+  let post = post.request_review().reject().request_review();
+  assert_eq!(Post::PendingReview, post.state());
+
+  let post = post.approve().ok().unwrap();
+  assert_eq!(Post::Published, post.state());
+  assert_eq!("Salad", post.data.title);
+}
+
+#[test]
+fn guards_give_the_machine_back() {
+  let post = post::Machine::new(article("")).request_review();
+
+  let mut post = post.approve().err().unwrap();
+  post.data.title.push_str("Salad");
+
+  assert!(post.approve().is_ok());
+}
+
+#[test]
+fn runtime_transitions() {
+  let mut post = post::Dynamic::new(article(""));
+  assert_eq!(&[post::Event::RequestReview], post.events());
+
+  let err = post.fire(post::Event::Approve).unwrap_err();
+  assert_eq!("Draft has no transition for approve", err.to_string());
+
+  post.fire(post::Event::RequestReview).unwrap();
+
+  let err = post.fire(post::Event::Approve).unwrap_err();
+  assert_eq!(Some("has_title"), err.guard);
+  assert_eq!(&Post::PendingReview, post.state());
+
+  post.data.title.push_str("Salad");
+  post.fire(post::Event::Approve).unwrap();
+  assert_eq!("Published", post.state_name());
+  assert!(post.events().is_empty());
+}
+
+#[test]
+fn typed_machines_become_dynamic() {
+  let post = post::Machine::new(article("Salad"))
+    .request_review()
+    .into_dynamic();
+
+  assert_eq!(&Post::PendingReview, post.state());
+}
+
+mod doors {
+  use hello_macro_derive::StateMachine;
+
+  // The module gets the visibility of the enum, pub(crate) here.
+  #[derive(Debug, PartialEq, StateMachine)]
+  #[state_machine(Closed => open => Open, Open => close => Closed)]
+  pub(crate) enum Door {
+    Closed,
+    Open,
+  }
+}
+
+#[test]
+fn modules_have_the_visibility_of_the_enum() {
+  let door = doors::door::Machine::new(()).open().close();
+
+  assert_eq!(doors::Door::Closed, door.state());
+}
+
+// A run of capitals is one word, the module is `http_request`.
+#[derive(Debug, PartialEq, StateMachine)]
+#[state_machine(Sent => answer => Answered)]
+pub enum HTTPRequest {
+  Sent,
+  Answered,
+}
+
+#[test]
+fn acronyms_are_one_word_in_module_names() {
+  let request = http_request::Machine::new(()).answer();
+
+  assert_eq!(HTTPRequest::Answered, request.state());
+}
+
+#[test]
+fn ui() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/state_machine_*.rs");
+}
@@ -0,0 +1,9 @@
+use hello_macro_derive::Builder;
+
+#[derive(Builder)]
+enum Shape {
+  Circle,
+  Square,
+}
+
+fn main() {}
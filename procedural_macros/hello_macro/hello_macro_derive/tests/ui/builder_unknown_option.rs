@@ -0,0 +1,9 @@
+use hello_macro_derive::Builder;
+
+#[derive(Builder)]
+struct Command {
+  #[builder(defualt)]
+  args: Vec<String>,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use hello_macro_derive::StateMachine;
+
+#[derive(StateMachine)]
+#[state_machine(
+  Draft => publish => Published,
+  Draft => publish => Draft,
+)]
+enum Post {
+  Draft,
+  Published,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use hello_macro_derive::StateMachine;
+
+#[derive(StateMachine)]
+#[state_machine(
+  Draft => do_it => Published,
+  Published => doIt => Draft,
+)]
+enum Post {
+  Draft,
+  Published,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use hello_macro_derive::StateMachine;
+
+#[derive(StateMachine)]
+#[state_machine(Open => close => Closed)]
+enum Match {
+  Open,
+  Closed,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use hello_macro_derive::StateMachine;
+
+#[derive(StateMachine)]
+#[state_machine(
+  Draft => state => Published,
+)]
+enum Post {
+  Draft,
+  Published,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use hello_macro_derive::StateMachine;
+
+#[derive(StateMachine)]
+#[state_machine(
+  Draft => request_review => Pending,
+)]
+enum Post {
+  Draft,
+  PendingReview,
+}
+
+fn main() {}
@@ -1,9 +1,82 @@
 use hello_macro::HelloMacro;
-use hello_macro_derive::HelloMacro;
+use hello_macro_derive::{Builder, HelloMacro, StateMachine};
 
 #[derive(HelloMacro)]
 struct Pancakes;
 
+#[derive(HelloMacro)]
+#[hello(name = "a stack of pancakes")]
+struct Stack<T: HelloMacro>(std::marker::PhantomData<T>);
+
+#[derive(Builder)]
+struct Order {
+  pancakes: u32,
+  #[builder(default)]
+  syrup: bool,
+  note: Option<String>,
+}
+
+// The same workflow as in type_state, without writing
+// a struct per state and an impl block per transition.
+#[derive(Debug, StateMachine)]
+#[state_machine(
+  data = String,
+  Draft => request_review => PendingReview,
+  PendingReview => approve if has_content => Published,
+  PendingReview => reject => Draft,
+)]
+enum Post {
+  Draft,
+  PendingReview,
+  Published,
+}
+
+fn has_content(content: &str) -> bool {
+  !content.is_empty()
+}
+
 fn main() {
   Pancakes::hello_macro();
+  Stack::<Pancakes>::hello_macro();
+
+  let orders = [
+    Order::builder()
+      .pancakes(3)
+      .note("extra crispy".to_string())
+      .build(),
+    // syrup can be left out, pancakes can't.
+    Order::builder().syrup(true).build(),
+  ];
+  for order in orders {
+    match order {
+      Ok(order) => println!(
+        "{} pancakes, syrup: {}, note: {:?}",
+        order.pancakes, order.syrup, order.note
+      ),
+      Err(e) => println!("{}", e),
+    }
+  }
+
+  let mut draft = post::Machine::new(String::new());
+  draft.data.push_str("I ate salad for lunch today");
+
+  // post.approve() wouldn't compile here, drafts can't be approved.
+  let post = match draft.request_review().approve() {
+    Ok(post) => post,
+    Err(_) => panic!("the post has content"),
+  };
+  println!("{:?}: {}", post.state(), post.data);
+
+  // When the events only show up at runtime, the checks do too.
+  let mut post = post::Dynamic::new(String::new());
+  for event in [
+    post::Event::Approve,
+    post::Event::RequestReview,
+    post::Event::Approve,
+  ] {
+    match post.fire(event) {
+      Ok(()) => println!("{:?} -> {:?}", event, post.state()),
+      Err(e) => println!("{}", e),
+    }
+  }
 }
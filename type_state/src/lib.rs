@@ -0,0 +1,128 @@
+// Encoding states and behaviour as types
+//
+// We will encode states and state transaitions
+// into different types. Consequently, Rust's type checking
+// system will prevent attempts to use draft posts
+// where only published posts are allowed by issuing
+// a compiler error.
+//
+//           request_review                  approve
+//                  │                           │
+//                  │                           │
+// ┌─────────────┐  │  ┌─────────────────────┐  │ ┌─────────────────┐
+// │             │  │  │                     │  │ │                 │
+// │ Post<Draft> ├──┴──► Post<PendingReview> ├──┴─► Post<Published> │
+// │             │     │                     │    │                 │
+// └──────┬──────┘     └──────────┬──────────┘    └────────┬────────┘
+//        │                       │                        │
+//        │                       │                        │
+//     add_text                approve                  content
+//     request_review
+//
+// There is a single Post type, generic over its state, so the
+// fields are only declared once. The states are zero-sized types:
+// they only exist at compile time and a Post<Draft> takes as much
+// memory as a Post<Published>.
+use std::marker::PhantomData;
+
+pub struct Draft;
+
+pub struct PendingReview;
+
+pub struct Published;
+
+/// A blog post in the state `S`.
+///
+/// Only published posts have content:
+///
+/// ```
+/// use type_state::{Draft, Post};
+///
+/// let mut post: Post<Draft> = Post::new();
+/// post.add_text("I ate salad for lunch today");
+///
+/// let post = post.request_review().approve();
+/// assert_eq!("I ate salad for lunch today", post.content());
+/// ```
+///
+/// Drafts don't:
+///
+/// ```compile_fail,E0599
+/// let post = type_state::Post::new();
+/// post.content();
+/// ```
+///
+/// Posts waiting for review can't be edited:
+///
+/// ```compile_fail,E0599
+/// let mut post = type_state::Post::new().request_review();
+/// post.add_text("too late");
+/// ```
+///
+/// And published posts can't be approved again:
+///
+/// ```compile_fail,E0599
+/// let post = type_state::Post::new().request_review().approve();
+/// post.approve();
+/// ```
+pub struct Post<S> {
+  content: String,
+  // Post doesn't hold an S, PhantomData tells the compiler
+  // it should behave as if it did.
+  state: PhantomData<S>,
+}
+
+// Methods every state has.
+impl<S> Post<S> {
+  // Moves the content into a post in another state.
+  fn into_state<T>(self) -> Post<T> {
+    Post {
+      content: self.content,
+      state: PhantomData,
+    }
+  }
+}
+
+impl Post<Draft> {
+  // [new] returns Post<Draft> because
+  // every post starts in the Draft state.
+  #[allow(clippy::new_without_default)]
+  pub fn new() -> Self {
+    Self {
+      content: String::new(),
+      state: PhantomData,
+    }
+  }
+
+  pub fn add_text(&mut self, text: &str) {
+    self.content.push_str(text);
+  }
+
+  pub fn request_review(self) -> Post<PendingReview> {
+    self.into_state()
+  }
+}
+
+impl Post<PendingReview> {
+  pub fn approve(self) -> Post<Published> {
+    self.into_state()
+  }
+}
+
+impl Post<Published> {
+  pub fn content(&self) -> &str {
+    &self.content
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::mem::size_of;
+
+  #[test]
+  fn states_take_no_space() {
+    assert_eq!(size_of::<String>(), size_of::<Post<Draft>>());
+    assert_eq!(size_of::<Post<Draft>>(), size_of::<Post<Published>>());
+  }
+}